@@ -1,21 +1,29 @@
 use std::error::Error;
 
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
     types::{
-        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
-		ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
-		ChatCompletionRequestMessage,
-        ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
-        CreateChatCompletionRequestArgs,
+        CreateChatCompletionStreamResponse, CreateCompletionRequestArgs, CreateCompletionResponse,
+        CreateEmbeddingResponse,
     },
     Client,
 };
-use futures::StreamExt;
-use serde_json::Value;
-use tauri::{AppHandle, Emitter};
-use crate::{configs::provider::Provider};
+use std::pin::Pin;
 
+use eventsource_stream::Eventsource;
+use futures::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crate::db::types::{Message, MessageRole};
+use crate::configs::{
+	model::{AudioParams, ImageGenerationParams, ModelInfo, ReasoningPolicy, TextModelCapability},
+	provider::Provider,
+};
+
+use crate::retry::{with_retry, ProviderCallError};
+use crate::scheduler::{Priority, Scheduler};
 use super::key_manager::KeyManager;
 
 
@@ -30,93 +38,739 @@ fn get_openai_client(
     Ok(Client::with_config(config))
 }
 
-/// Converts generic message values to OpenAI-compatible message types
-fn convert_messages(
-    messages: Vec<Value>,
-) -> Result<Vec<ChatCompletionRequestMessage>, Box<dyn Error>> {
-    messages
-        .into_iter()
-        .map(|msg| {
-            let role = msg["role"].as_str().ok_or("Missing role")?;
-            let content = msg["content"].as_str().ok_or("Missing content")?;
-
-            match role {
-                "user" => Ok(ChatCompletionRequestMessage::User(
-                    ChatCompletionRequestUserMessage {
-                        content: ChatCompletionRequestUserMessageContent::Text(content.to_string()),
-                        ..Default::default()
-                    },
-                )),
-                "assistant" => Ok(ChatCompletionRequestMessage::Assistant(
-                    ChatCompletionRequestAssistantMessage {
-                        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
-                            content.to_string(),
-                        )),
-                        ..Default::default()
-                    },
-                )),
-                "system" => Ok(ChatCompletionRequestMessage::System(
-                    ChatCompletionRequestSystemMessage {
-                        content: ChatCompletionRequestSystemMessageContent::Text(
-                            content.to_string(),
-                        ),
-                        ..Default::default()
-                    },
-                )),
-                // Add other roles (assistant, system) as needed
-                _ => Err("Unsupported role".into()),
-            }
-        })
-        .collect()
-}
-
-/// Streams chat completions from OpenAI-compatible API and emits chunks via Tauri
-pub async fn ask_openai_stream(
-    app_handle: AppHandle,
-    messages: Vec<Value>,
-    model: String,
-	provider: Provider,
-) -> Result<(), Box<dyn Error>> {
-	let base_url = provider.base_url;
+/// Creates a client for a provider, reading its API key from the keyring
+fn get_provider_client(provider: &Provider) -> Result<Client<OpenAIConfig>, Box<dyn Error>> {
 	let key_manager_local = KeyManager::new("wisp".to_string());
 	let api_key = key_manager_local.get_api_key(&provider.name).or_else(|_| std::env::var("OPENAI_API_KEY"))?;
-	let client = get_openai_client(base_url, api_key)?;
-    let converted_messages = convert_messages(messages)?;
+	get_openai_client(provider.base_url.clone(), api_key)
+}
 
-    let request = CreateChatCompletionRequestArgs::default()
-        .max_tokens(1024u32)
-		.model(model)
-        .messages(converted_messages)
-        .stream(true)
-        .build()?;
+/// The full text of a streamed reply
+#[derive(Debug, Default)]
+pub struct StreamedReply {
+	pub text: String,
+	pub reasoning: String,
+}
+
+/// How previous reasoning is sent back to `model`; unconfigured models drop it
+pub fn reasoning_policy(provider: &Provider, model: &str) -> ReasoningPolicy {
+	match provider.get_model(model).map(|m| &m.model_info) {
+		Some(ModelInfo::TextGeneration { reasoning_policy, .. }) => *reasoning_policy,
+		_ => ReasoningPolicy::Drop,
+	}
+}
 
-    let mut stream = client.chat().create_stream(request).await?;
+/// Builds the messages of a chat request from stored messages, sending the
+/// reasoning of bot messages back according to `policy`.
+/// Everything above the last compaction point except system prompts is
+/// replaced by its summary.
+pub fn chat_request_messages(messages: &[Message], policy: ReasoningPolicy) -> Vec<Value> {
+	let cut = messages.iter().rposition(|m| m.compaction.is_some());
+	let mut request = vec![];
+	for (index, m) in messages.iter().enumerate() {
+		match (cut, &m.compaction) {
+			(Some(cut), Some(compaction)) if index == cut => request.push(json!({
+				"role": "system",
+				"content": format!("Summary of the earlier conversation:\n\n{}", compaction.summary),
+			})),
+			(Some(cut), _) if index < cut && !matches!(m.sender, MessageRole::System) => continue,
+			_ => {}
+		}
+		request.push(chat_request_message(m, policy));
+	}
+	request
+}
+
+fn chat_request_message(m: &Message, policy: ReasoningPolicy) -> Value {
+	let role = m.sender.api_role();
+	let reasoning = m.reasoning.as_deref().filter(|r| !r.is_empty());
+	match (&m.sender, reasoning, policy) {
+		(MessageRole::Assistant, Some(reasoning), ReasoningPolicy::Keep) => json!({
+			"role": role,
+			"content": m.text,
+			"reasoning_content": reasoning,
+		}),
+		(MessageRole::Assistant, Some(reasoning), ReasoningPolicy::ThinkingBlocks) => json!({
+			"role": role,
+			"content": [
+				{ "type": "thinking", "thinking": reasoning },
+				{ "type": "text", "text": m.text },
+			],
+		}),
+		_ => json!({ "role": role, "content": m.text }),
+	}
+}
+
+/// Parses messages sent by the frontend. Roles may use either the stored
+/// (`bot`) or the API (`assistant`) name, and compaction points are kept.
+pub fn messages_from_values(values: Vec<Value>) -> Result<Vec<Message>, Box<dyn Error>> {
+	values
+		.into_iter()
+		.map(|value| {
+			let role = value["role"].as_str().ok_or("Missing role")?;
+			let content = value["content"].as_str().ok_or("Missing content")?;
+			Ok(Message {
+				id: String::new(),
+				text: content.to_string(),
+				reasoning: value["reasoning"].as_str().map(|s| s.to_string()),
+				sender: MessageRole::try_from(role.to_string())?,
+				timestamp: 0,
+				tokens: None,
+				embedding: None,
+				compaction: serde_json::from_value(value["compaction"].clone()).ok(),
+			})
+		})
+		.collect()
+}
+
+/// A streamed chunk of one of the choices of a completion
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamChunk {
+	pub index: u32,
+	pub delta: String,
+}
+
+/// A streamed chunk tagged with the message and model it belongs to, for when
+/// several replies stream at once
+#[derive(Debug, Clone, Serialize)]
+pub struct TaggedStreamChunk {
+	pub message_id: String,
+	pub provider: String,
+	pub model: String,
+	pub delta: String,
+}
+
+/// Which part of a reply a streamed chunk belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamChannel {
+	Content,
+	Reasoning,
+}
+
+impl StreamedReply {
+	fn push(&mut self, channel: StreamChannel, delta: &str) {
+		match channel {
+			StreamChannel::Content => self.text.push_str(delta),
+			StreamChannel::Reasoning => self.reasoning.push_str(delta),
+		}
+	}
+}
+
+/// Whether the streamed content of `model` carries its reasoning inline in
+/// `<think>` tags
+fn parses_think_tags(provider: &Provider, model: &str) -> bool {
+	matches!(
+		provider.get_model(model).map(|m| &m.model_info),
+		Some(ModelInfo::TextGeneration { parse_think_tags: true, .. })
+	)
+}
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Splits streamed content into reply text and reasoning at `<think>` tags.
+/// Text that may be the start of a tag split across chunks is held back until
+/// the next chunk decides it.
+#[derive(Debug, Default)]
+struct ThinkTagParser {
+	in_think: bool,
+	after_think: bool,
+	pending: String,
+}
+
+impl ThinkTagParser {
+	fn push(&mut self, chunk: &str) -> Vec<(StreamChannel, String)> {
+		self.pending.push_str(chunk);
+		let mut parts = Vec::new();
+
+		loop {
+			let tag = if self.in_think { THINK_CLOSE } else { THINK_OPEN };
+			match self.pending.find(tag) {
+				Some(position) => {
+					let before: String = self.pending.drain(..position).collect();
+					self.pending.drain(..tag.len());
+					self.emit(&mut parts, before);
+					self.after_think = self.in_think;
+					self.in_think = !self.in_think;
+				}
+				None => {
+					let keep = partial_tag_len(&self.pending, tag);
+					let ready: String = self.pending.drain(..self.pending.len() - keep).collect();
+					self.emit(&mut parts, ready);
+					return parts;
+				}
+			}
+		}
+	}
+
+	/// Flushes held back text once the stream has ended
+	fn finish(&mut self) -> Vec<(StreamChannel, String)> {
+		let mut parts = Vec::new();
+		let rest = std::mem::take(&mut self.pending);
+		self.emit(&mut parts, rest);
+		parts
+	}
+
+	fn emit(&mut self, parts: &mut Vec<(StreamChannel, String)>, text: String) {
+		if self.in_think {
+			if !text.is_empty() {
+				parts.push((StreamChannel::Reasoning, text));
+			}
+			return;
+		}
+
+		// Drop the blank lines models put between the reasoning and the reply
+		let text = if self.after_think {
+			text.trim_start().to_string()
+		} else {
+			text
+		};
+		if !text.is_empty() {
+			self.after_think = false;
+			parts.push((StreamChannel::Content, text));
+		}
+	}
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+	(1..tag.len())
+		.rev()
+		.find(|&len| text.ends_with(&tag[..len]))
+		.unwrap_or(0)
+}
+
+/// Streams a chat completion with `n` choices, handing every chunk to
+/// `on_chunk` with its choice index as it arrives. Returns one reply per choice.
+/// Failed requests and streams that break before their first token are retried
+/// according to the provider's retry policy.
+pub async fn stream_chat<F>(
+	app_handle: &AppHandle,
+	messages: Vec<Value>,
+	model: String,
+	provider: &Provider,
+	n: u8,
+	priority: Priority,
+	mut on_chunk: F,
+) -> Result<Vec<StreamedReply>, Box<dyn Error>>
+where
+	F: FnMut(u32, StreamChannel, String) -> Result<(), String> + Send,
+{
+	let mut request = json!({
+		"model": model,
+		"messages": messages,
+		"stream": true,
+	});
+	if let Some(ModelInfo::TextGeneration { parameters, .. }) = provider.get_model(&model).map(|m| &m.model_info) {
+		if let Some(max_tokens) = parameters.max_tokens {
+			request["max_tokens"] = max_tokens.into();
+		}
+	}
+	if n > 1 {
+		request["n"] = n.into();
+	}
+
+	let (head, rest) = open_stream::<CreateChatCompletionStreamResponse>(
+		app_handle,
+		provider,
+		"/chat/completions",
+		&request,
+		priority,
+		|chunk| chunk.choices.iter().any(|c| {
+			c.delta.content.as_deref().is_some_and(|s| !s.is_empty())
+				|| c.delta.reasoning_content.as_deref().is_some_and(|s| !s.is_empty())
+		}),
+	)
+	.await?;
+	let mut stream = futures::stream::iter(head.into_iter().map(Ok)).chain(rest);
+
+	let think_tags = parses_think_tags(provider, &model);
+	let mut replies: Vec<StreamedReply> = (0..n.max(1)).map(|_| StreamedReply::default()).collect();
+	let mut parsers: Vec<ThinkTagParser> = (0..n.max(1)).map(|_| ThinkTagParser::default()).collect();
 
     while let Some(response) = stream.next().await {
 		match response {
             Ok(ccr) => {
                 for choice in ccr.choices {
+					let index = choice.index as usize;
+					// The index comes from the server; never grow past the choices asked for
+					if index >= replies.len() {
+						log::warn!("Skipping chunk for unrequested choice {}", choice.index);
+						continue;
+					}
                     if let Some(content) = choice.delta.content {
-                        app_handle
-                            .emit("openai_stream_chunk", content)
-                            .map_err(|e| e.to_string())?;
+						let parts = if think_tags {
+							parsers[index].push(&content)
+						} else {
+							vec![(StreamChannel::Content, content)]
+						};
+						for (channel, delta) in parts {
+							replies[index].push(channel, &delta);
+							on_chunk(choice.index, channel, delta)?;
+						}
                     }
 					if let Some(reasoning_content) = choice.delta.reasoning_content {
-                        app_handle
-                            .emit("openai_stream_chunk_reasoning", reasoning_content)
-                            .map_err(|e| e.to_string())?;
+						replies[index].reasoning.push_str(&reasoning_content);
+						on_chunk(choice.index, StreamChannel::Reasoning, reasoning_content)?;
                     }
                 }
             }
+            // Part of the reply has already been streamed, so this fails the
+            // call instead of moving on to a fallback or keeping a cut-off reply
             Err(e) => {
-                eprintln!("Error in stream chunk: {}", e);
-                // Log the raw error message for debugging
-                if let Some(raw_error) = e.source() {
-                    eprintln!("Raw error: {}", raw_error);
-                }
+                log::error!("Chat stream failed: {}", e);
+                return Err(format!("Chat stream failed: {}", e).into());
             }
         }
     }
 
-    Ok(())
+	for (index, parser) in parsers.iter_mut().enumerate() {
+		for (channel, delta) in parser.finish() {
+			replies[index].push(channel, &delta);
+			on_chunk(index as u32, channel, delta)?;
+		}
+	}
+
+    Ok(replies)
+}
+
+/// Streams chat completions from OpenAI-compatible API and emits chunks via Tauri
+pub async fn ask_openai_stream(
+    app_handle: AppHandle,
+    messages: Vec<Value>,
+    model: String,
+	provider: Provider,
+	n: u8,
+) -> Result<Vec<StreamedReply>, Box<dyn Error>> {
+	stream_chat(&app_handle, messages, model, &provider, n, Priority::Interactive, |index, channel, delta| {
+		let event = match channel {
+			StreamChannel::Content => "openai_stream_chunk",
+			StreamChannel::Reasoning => "openai_stream_chunk_reasoning",
+		};
+		app_handle
+			.emit(event, StreamChunk { index, delta })
+			.map_err(|e| e.to_string())
+	})
+	.await
+}
+
+/// Streams a fill-in-the-middle completion between `prefix` and `suffix` from
+/// the provider's completions endpoint, emitting chunks like `ask_openai_stream`
+pub async fn complete_fim(
+	app_handle: AppHandle,
+	prefix: String,
+	suffix: String,
+	model: String,
+	provider: Provider,
+) -> Result<(), Box<dyn Error>> {
+	let params = match provider.get_model(&model).map(|m| &m.model_info) {
+		Some(ModelInfo::TextGeneration { parameters, capabilities, .. })
+			if capabilities.contains(&TextModelCapability::FIM) => parameters.clone(),
+		Some(_) => return Err(format!("{} does not support fill-in-the-middle", model).into()),
+		None => return Err(format!("Model {} not found", model).into()),
+	};
+	let mut args = CreateCompletionRequestArgs::default();
+	args.model(model)
+		.prompt(prefix)
+		.suffix(suffix)
+		.max_tokens(params.max_tokens.map(|t| t as u32).unwrap_or(256))
+		.stream(true);
+	if let Some(temperature) = params.temperature {
+		args.temperature(temperature);
+	}
+	if let Some(top_p) = params.top_p {
+		args.top_p(top_p);
+	}
+	if let Some(stop_sequences) = params.stop_sequences {
+		args.stop(stop_sequences);
+	}
+	let request = serde_json::to_value(args.build()?)?;
+
+	let (head, rest) = open_stream::<CreateCompletionResponse>(
+		&app_handle,
+		&provider,
+		"/completions",
+		&request,
+		Priority::Interactive,
+		|chunk| chunk.choices.iter().any(|c| !c.text.is_empty()),
+	)
+	.await?;
+	let mut stream = futures::stream::iter(head.into_iter().map(Ok)).chain(rest);
+
+	// A broken stream fails the command, so the frontend learns the completion
+	// stopped short
+	while let Some(response) = stream.next().await {
+		let ccr = response.map_err(|e| format!("Completion stream failed: {}", e))?;
+		for choice in ccr.choices {
+			if !choice.text.is_empty() {
+				let chunk = StreamChunk { index: choice.index, delta: choice.text };
+				app_handle
+					.emit("openai_stream_chunk", chunk)
+					.map_err(|e| e.to_string())?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Embeds a batch of texts with an OpenAI-compatible embeddings endpoint,
+/// returning one vector per input in input order
+pub async fn create_embeddings(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	priority: Priority,
+	model: &str,
+	dimensions: Option<u32>,
+	inputs: Vec<String>,
+) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+	let mut body = json!({
+		"model": model,
+		"input": inputs,
+	});
+	if let Some(dimensions) = dimensions {
+		body["dimensions"] = dimensions.into();
+	}
+
+	let response = post_provider(app_handle, provider, "/embeddings", &body, priority).await?;
+	let mut data = response.json::<CreateEmbeddingResponse>().await?.data;
+	data.sort_by_key(|e| e.index);
+	Ok(data.into_iter().map(|e| e.embedding).collect())
+}
+
+/// Posts a JSON body to a provider endpoint, failing on non-success status codes
+async fn send_request(
+	provider: &Provider,
+	path: &str,
+	body: &Value,
+) -> Result<reqwest::Response, ProviderCallError> {
+	send_with(provider, path, |request| request.json(body)).await
+}
+
+/// Posts to a provider endpoint with the body set by `with_body`, failing on
+/// non-success status codes
+async fn send_with(
+	provider: &Provider,
+	path: &str,
+	with_body: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, ProviderCallError> {
+	let client = get_provider_client(provider).map_err(|e| ProviderCallError::Client(e.to_string()))?;
+	let config = client.config();
+
+	let request = reqwest::Client::new()
+		.post(config.url(path))
+		.headers(config.headers());
+	let response = with_body(request).send().await?;
+
+	let status = response.status();
+	if !status.is_success() {
+		let retry_after = crate::retry::retry_after(response.headers());
+		let body = response.text().await.unwrap_or_default();
+		return Err(ProviderCallError::Status {
+			path: path.to_string(),
+			status,
+			body,
+			retry_after,
+		});
+	}
+	Ok(response)
+}
+
+/// Posts a JSON body to a provider endpoint once the scheduler lets it
+/// through, retrying transient failures according to the provider's retry policy
+async fn post_provider(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	path: &str,
+	body: &Value,
+	priority: Priority,
+) -> Result<reqwest::Response, ProviderCallError> {
+	let scheduler = Scheduler::clone(&app_handle.state::<Scheduler>());
+	with_retry(app_handle, provider, || async {
+		let _permit = scheduler.acquire(provider, priority, estimate_tokens(body)).await;
+		send_request(provider, path, body).await
+	})
+	.await
+}
+
+/// Rough token count of a request: its size in characters over four, plus the
+/// reply limit when it sets one
+fn estimate_tokens(body: &Value) -> u32 {
+	let prompt = body.to_string().len() as u32 / 4;
+	let reply = body["max_tokens"].as_u64().unwrap_or(0) as u32;
+	prompt.saturating_add(reply)
+}
+
+type EventStream<R> = Pin<Box<dyn Stream<Item = Result<R, ProviderCallError>> + Send>>;
+
+/// Sends a streaming request and parses the server-sent events of the response
+async fn send_stream_request<R>(
+	provider: &Provider,
+	path: &str,
+	body: &Value,
+) -> Result<EventStream<R>, ProviderCallError>
+where
+	R: DeserializeOwned + Send + 'static,
+{
+	let response = send_request(provider, path, body).await?;
+	let events = response
+		.bytes_stream()
+		.eventsource()
+		.take_while(|event| futures::future::ready(!matches!(event, Ok(e) if e.data == "[DONE]")))
+		.map(|event| {
+			let event = event.map_err(|e| ProviderCallError::Stream(e.to_string()))?;
+			serde_json::from_str::<R>(&event.data)
+				.map_err(|e| ProviderCallError::InvalidResponse(format!("{}: {}", e, event.data)))
+		});
+	Ok(Box::pin(events))
+}
+
+/// Opens a stream once the scheduler lets it through, retrying when the request
+/// fails or the stream breaks before its first token. Returns the chunks read
+/// up to and including the first token, followed by the rest of the stream,
+/// which holds its scheduler permit until dropped.
+async fn open_stream<R>(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	path: &str,
+	body: &Value,
+	priority: Priority,
+	is_token: fn(&R) -> bool,
+) -> Result<(Vec<R>, EventStream<R>), ProviderCallError>
+where
+	R: DeserializeOwned + Send + 'static,
+{
+	let scheduler = Scheduler::clone(&app_handle.state::<Scheduler>());
+	let tokens = estimate_tokens(body);
+	with_retry(app_handle, provider, || async {
+		let permit = scheduler.acquire(provider, priority, tokens).await;
+		let events = send_stream_request::<R>(provider, path, body).await?;
+		let mut events: EventStream<R> = Box::pin(events.map(move |event| {
+			let _ = &permit;
+			event
+		}));
+		let mut head = Vec::new();
+		while let Some(chunk) = events.next().await {
+			let chunk = chunk?;
+			let has_token = is_token(&chunk);
+			head.push(chunk);
+			if has_token {
+				break;
+			}
+		}
+		Ok((head, events))
+	})
+	.await
+}
+
+pub struct GeneratedImage {
+	pub data: Vec<u8>,
+	pub mime_type: String,
+	pub revised_prompt: Option<String>,
+}
+
+/// Guesses the MIME type of an image from its magic bytes
+fn sniff_image_mime(data: &[u8]) -> &'static str {
+	if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+		"image/png"
+	} else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+		"image/jpeg"
+	} else if data.len() > 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+		"image/webp"
+	} else if data.starts_with(b"GIF8") {
+		"image/gif"
+	} else {
+		"application/octet-stream"
+	}
+}
+
+/// Builds the request body for an OpenAI-compatible `/images/generations`
+/// endpoint. Fields beyond the OpenAI schema are only sent when set, for the
+/// servers that understand them.
+pub fn image_generation_body(model: &str, prompt: &str, params: &ImageGenerationParams) -> Value {
+	let mut body = serde_json::json!({
+		"model": model,
+		"prompt": prompt,
+		"n": 1,
+		"response_format": "b64_json",
+	});
+	if params.width > 0 && params.height > 0 {
+		body["size"] = format!("{}x{}", params.width, params.height).into();
+	}
+	if params.steps > 0 {
+		body["steps"] = params.steps.into();
+	}
+	if params.cfg_scale > 0.0 {
+		body["cfg_scale"] = params.cfg_scale.into();
+	}
+	if let Some(sampler) = &params.sampler {
+		body["sampler"] = sampler.clone().into();
+	}
+	if let Some(style_preset) = &params.style_preset {
+		body["style_preset"] = style_preset.clone().into();
+	}
+	body
+}
+
+/// Generates images with an OpenAI-compatible `/images/generations` endpoint
+pub async fn generate_images(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	body: &Value,
+) -> Result<Vec<GeneratedImage>, Box<dyn Error>> {
+	let response = post_provider(app_handle, provider, "/images/generations", body, Priority::Interactive).await?;
+	let response = response.json::<Value>().await?;
+	let items = response["data"].as_array().cloned().unwrap_or_default();
+
+	let mut images = Vec::with_capacity(items.len());
+	for item in items {
+		let data = if let Some(b64_json) = item["b64_json"].as_str() {
+			STANDARD.decode(b64_json)?
+		} else if let Some(url) = item["url"].as_str() {
+			reqwest::get(url).await?.error_for_status()?.bytes().await?.to_vec()
+		} else {
+			return Err("Image response contains neither b64_json nor url".into());
+		};
+		images.push(GeneratedImage {
+			mime_type: sniff_image_mime(&data).to_string(),
+			revised_prompt: item["revised_prompt"].as_str().map(|s| s.to_string()),
+			data,
+		});
+	}
+	Ok(images)
+}
+
+/// Transcribes one audio file with an OpenAI-compatible `/audio/transcriptions` endpoint
+pub async fn transcribe_audio(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	model: &str,
+	file_name: &str,
+	data: &[u8],
+	language: Option<String>,
+) -> Result<String, Box<dyn Error>> {
+	let scheduler = Scheduler::clone(&app_handle.state::<Scheduler>());
+	let response = with_retry(app_handle, provider, || async {
+		let _permit = scheduler.acquire(provider, Priority::Interactive, 0).await;
+		// A multipart form can only be sent once, so each attempt builds its own
+		let file = reqwest::multipart::Part::bytes(data.to_vec()).file_name(file_name.to_string());
+		let mut form = reqwest::multipart::Form::new()
+			.part("file", file)
+			.text("model", model.to_string());
+		if let Some(language) = &language {
+			form = form.text("language", language.clone());
+		}
+		send_with(provider, "/audio/transcriptions", |request| request.multipart(form)).await
+	})
+	.await?;
+
+	let response = response.json::<Value>().await?;
+	response["text"]
+		.as_str()
+		.map(|text| text.to_string())
+		.ok_or_else(|| "Transcription response contains no text".into())
+}
+
+/// Builds the request body for an OpenAI-compatible `/audio/speech` endpoint
+pub fn speech_body(model: &str, input: &str, params: &AudioParams) -> Value {
+	let mut body = serde_json::json!({
+		"model": model,
+		"input": input,
+		"voice": params.voice.clone().unwrap_or_else(|| "alloy".to_string()),
+		"response_format": params.response_format.clone().unwrap_or_else(|| "mp3".to_string()),
+	});
+	if let Some(speed) = params.speed {
+		body["speed"] = speed.into();
+	}
+	body
+}
+
+/// Renders text to audio with an OpenAI-compatible `/audio/speech` endpoint
+pub async fn synthesize_speech(
+	app_handle: &AppHandle,
+	provider: &Provider,
+	body: &Value,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+	let response = post_provider(app_handle, provider, "/audio/speech", body, Priority::Interactive).await?;
+	Ok(response.bytes().await?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Feeds `chunks` through a parser and returns the reasoning and the reply text
+	fn parse(chunks: &[&str]) -> (String, String) {
+		let mut parser = ThinkTagParser::default();
+		let mut reasoning = String::new();
+		let mut content = String::new();
+		let parts = chunks
+			.iter()
+			.flat_map(|chunk| parser.push(chunk))
+			.collect::<Vec<_>>()
+			.into_iter()
+			.chain(parser.finish());
+		for (channel, text) in parts {
+			match channel {
+				StreamChannel::Reasoning => reasoning.push_str(&text),
+				StreamChannel::Content => content.push_str(&text),
+			}
+		}
+		(reasoning, content)
+	}
+
+	#[test]
+	fn think_tags_split_at_every_offset() {
+		let text = "<think>Step one</think>\n\nThe answer";
+		for at in 0..=text.len() {
+			let (head, tail) = text.split_at(at);
+			assert_eq!(
+				parse(&[head, tail]),
+				("Step one".to_string(), "The answer".to_string()),
+				"split at {}",
+				at
+			);
+		}
+	}
+
+	#[test]
+	fn think_tags_one_byte_per_chunk() {
+		let text = "<think>a < b</think>done";
+		let chunks: Vec<String> = text.chars().map(String::from).collect();
+		let chunks: Vec<&str> = chunks.iter().map(String::as_str).collect();
+		assert_eq!(parse(&chunks), ("a < b".to_string(), "done".to_string()));
+	}
+
+	#[test]
+	fn unclosed_think_tag_is_all_reasoning() {
+		assert_eq!(
+			parse(&["<think>still ", "thinking"]),
+			("still thinking".to_string(), String::new())
+		);
+	}
+
+	#[test]
+	fn partial_tag_at_the_end_is_flushed_as_text() {
+		assert_eq!(parse(&["Hello <thi"]), (String::new(), "Hello <thi".to_string()));
+		assert_eq!(
+			parse(&["<think>hm</thi"]),
+			("hm</thi".to_string(), String::new())
+		);
+	}
+
+	#[test]
+	fn text_before_and_after_think_tags() {
+		assert_eq!(
+			parse(&["Sure. <think>", "why not", "</think>\n\nHere it is"]),
+			("why not".to_string(), "Sure. Here it is".to_string())
+		);
+	}
+
+	#[test]
+	fn text_without_think_tags_is_content() {
+		assert_eq!(
+			parse(&["plain ", "<b>text</b>"]),
+			(String::new(), "plain <b>text</b>".to_string())
+		);
+	}
 }
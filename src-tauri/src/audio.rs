@@ -0,0 +1,381 @@
+/// Layout of the PCM data in a WAV file.
+struct WavFormat<'a> {
+    fmt_chunk: &'a [u8],
+    byte_rate: u32,
+    block_align: u16,
+    data: &'a [u8],
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn parse_wav(bytes: &[u8]) -> Option<WavFormat<'_>> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut fmt_chunk = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(bytes, offset + 4)? as usize;
+        let body_start = offset + 8;
+        let body_end = (body_start + size).min(bytes.len());
+        match id {
+            b"fmt " => fmt_chunk = Some(&bytes[body_start..body_end]),
+            b"data" => data = Some(&bytes[body_start..body_end]),
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset = body_start + size + (size & 1);
+    }
+
+    let fmt_chunk = fmt_chunk?;
+    Some(WavFormat {
+        fmt_chunk,
+        byte_rate: read_u32(fmt_chunk, 8)?,
+        block_align: read_u16(fmt_chunk, 12)?,
+        data: data?,
+    })
+}
+
+fn build_wav(fmt_chunk: &[u8], data: &[u8]) -> Vec<u8> {
+    let riff_size = 4 + (8 + fmt_chunk.len()) + (8 + data.len());
+    let mut wav = Vec::with_capacity(riff_size + 8);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(riff_size as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&(fmt_chunk.len() as u32).to_le_bytes());
+    wav.extend_from_slice(fmt_chunk);
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(data);
+    wav
+}
+
+/// Duration of a WAV file in seconds, or `None` if it cannot be parsed.
+pub fn wav_duration(bytes: &[u8]) -> Option<f32> {
+    let wav = parse_wav(bytes)?;
+    if wav.byte_rate == 0 {
+        return None;
+    }
+    Some(wav.data.len() as f32 / wav.byte_rate as f32)
+}
+
+/// Split a WAV file into standalone WAV files of at most `max_duration`
+/// seconds each, cutting on sample frame boundaries. Returns `None` if the
+/// file is not a WAV file this can parse.
+pub fn split_wav(bytes: &[u8], max_duration: f32) -> Option<Vec<Vec<u8>>> {
+    let wav = parse_wav(bytes)?;
+    let block_align = wav.block_align.max(1) as usize;
+    let frames_per_part = ((max_duration * wav.byte_rate as f32) as usize / block_align).max(1);
+    let part_len = frames_per_part * block_align;
+
+    Some(
+        wav.data
+            .chunks(part_len)
+            .map(|part| build_wav(wav.fmt_chunk, part))
+            .collect(),
+    )
+}
+
+/// Frames of an MPEG audio stream, which can be cut between any two frames.
+struct Mp3Stream<'a> {
+    frames: Vec<&'a [u8]>,
+    sample_rate: u32,
+    samples_per_frame: u32,
+}
+
+/// Bitrates in kbit/s by version (MPEG-1 or 2/2.5), layer and bitrate index.
+const MP3_BITRATES: [[[u16; 15]; 3]; 2] = [
+    [
+        [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    ],
+    [
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+
+/// Length, sample rate and samples per frame of the MPEG audio frame at the
+/// start of `bytes`.
+fn mp3_frame_header(bytes: &[u8]) -> Option<(usize, u32, u32)> {
+    let header = bytes.get(0..4)?;
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 3;
+    let layer = (header[1] >> 1) & 3;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 3) as usize;
+    let padding = ((header[2] >> 1) & 1) as u32;
+    // Reserved values, and free format streams whose frame length is unknown
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+
+    let mpeg1 = version == 3;
+    let layer_index = (3 - layer) as usize;
+    let bitrate = MP3_BITRATES[!mpeg1 as usize][layer_index][bitrate_index] as u32 * 1000;
+    let sample_rate = [44100, 48000, 32000][sample_rate_index] >> match version {
+        3 => 0,
+        2 => 1,
+        _ => 2,
+    };
+    let (samples_per_frame, length) = match layer_index {
+        0 => (384, (12 * bitrate / sample_rate + padding) * 4),
+        1 => (1152, 144 * bitrate / sample_rate + padding),
+        _ if mpeg1 => (1152, 144 * bitrate / sample_rate + padding),
+        _ => (576, 72 * bitrate / sample_rate + padding),
+    };
+    Some((length as usize, sample_rate, samples_per_frame))
+}
+
+fn parse_mp3(bytes: &[u8]) -> Option<Mp3Stream<'_>> {
+    // Skip an ID3v2 tag, whose size is stored in 7-bit bytes
+    let mut offset = 0;
+    if bytes.get(0..3)? == b"ID3" {
+        let size = bytes
+            .get(6..10)?
+            .iter()
+            .fold(0usize, |size, b| (size << 7) | (b & 0x7F) as usize);
+        let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+        offset = 10 + size + footer;
+    }
+
+    let (_, sample_rate, samples_per_frame) = mp3_frame_header(bytes.get(offset..)?)?;
+    let mut frames = Vec::new();
+    // Stop at the first thing that is not a frame of the same stream, such as
+    // an ID3v1 tag at the end
+    while let Some((length, rate, samples)) = bytes.get(offset..).and_then(mp3_frame_header) {
+        if (rate, samples) != (sample_rate, samples_per_frame) || offset + length > bytes.len() {
+            break;
+        }
+        frames.push(&bytes[offset..offset + length]);
+        offset += length;
+    }
+
+    if frames.is_empty() {
+        return None;
+    }
+    Some(Mp3Stream {
+        frames,
+        sample_rate,
+        samples_per_frame,
+    })
+}
+
+/// Split an MP3 file into MP3 files of at most `max_duration` seconds each,
+/// cutting between frames. Returns `None` if the file is not an MPEG audio
+/// stream this can parse.
+pub fn split_mp3(bytes: &[u8], max_duration: f32) -> Option<Vec<Vec<u8>>> {
+    let mp3 = parse_mp3(bytes)?;
+    let frame_duration = mp3.samples_per_frame as f32 / mp3.sample_rate as f32;
+    let frames_per_part = ((max_duration / frame_duration) as usize).max(1);
+
+    Some(
+        mp3.frames
+            .chunks(frames_per_part)
+            .map(|part| part.concat())
+            .collect(),
+    )
+}
+
+/// Duration of a WAV or MP3 file in seconds, or `None` if it is in another
+/// format or cannot be parsed.
+pub fn duration(bytes: &[u8]) -> Option<f32> {
+    wav_duration(bytes).or_else(|| {
+        let mp3 = parse_mp3(bytes)?;
+        Some((mp3.frames.len() as u64 * mp3.samples_per_frame as u64) as f32 / mp3.sample_rate as f32)
+    })
+}
+
+/// Split a WAV or MP3 file into parts of at most `max_duration` seconds each.
+/// Returns `None` if it is in another format or cannot be parsed.
+pub fn split(bytes: &[u8], max_duration: f32) -> Option<Vec<Vec<u8>>> {
+    split_wav(bytes, max_duration).or_else(|| split_mp3(bytes, max_duration))
+}
+
+/// MIME type of an audio file from its extension or response format name.
+pub fn mime_type_for_extension(extension: &str) -> &'static str {
+    match extension.to_lowercase().as_str() {
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "aac" => "audio/aac",
+        "m4a" | "mp4" => "audio/mp4",
+        "ogg" | "opus" => "audio/ogg",
+        "webm" => "audio/webm",
+        "pcm" => "audio/L16",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PCM WAV file of `frames` sample frames, with an optional chunk of
+    /// odd size before the data
+    fn wav(channels: u16, sample_rate: u32, bits: u16, frames: usize, extra_chunk: bool) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut fmt_chunk = Vec::new();
+        fmt_chunk.extend_from_slice(&1u16.to_le_bytes());
+        fmt_chunk.extend_from_slice(&channels.to_le_bytes());
+        fmt_chunk.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt_chunk.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        fmt_chunk.extend_from_slice(&block_align.to_le_bytes());
+        fmt_chunk.extend_from_slice(&bits.to_le_bytes());
+        let data: Vec<u8> = (0..frames * block_align as usize).map(|i| i as u8).collect();
+
+        let mut wav = build_wav(&fmt_chunk, &data);
+        if extra_chunk {
+            // Chunks of odd size are followed by a padding byte
+            let mut chunk = b"LIST\x03\x00\x00\x00abc\x00".to_vec();
+            let fmt_end = 12 + 8 + fmt_chunk.len();
+            chunk.extend_from_slice(&wav[fmt_end..]);
+            wav.truncate(fmt_end);
+            wav.extend_from_slice(&chunk);
+            let riff_size = (wav.len() - 8) as u32;
+            wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        }
+        wav
+    }
+
+    /// Data of a WAV file written by `build_wav`, checking its header sizes
+    fn wav_data(wav: &[u8]) -> &[u8] {
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(read_u32(wav, 4).unwrap() as usize, wav.len() - 8);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(read_u32(wav, 40).unwrap() as usize, wav.len() - 44);
+        &wav[44..]
+    }
+
+    #[test]
+    fn wav_duration_from_header() {
+        assert_eq!(wav_duration(&wav(1, 8000, 16, 16000, false)), Some(2.0));
+        assert_eq!(wav_duration(&wav(2, 44100, 16, 22050, true)), Some(0.5));
+        assert_eq!(wav_duration(b"RIFF\x00\x00\x00\x00AVI "), None);
+    }
+
+    #[test]
+    fn wav_splits_at_max_duration() {
+        let original = wav(1, 8000, 16, 16000, true);
+        let parts = split_wav(&original, 1.0).unwrap();
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert_eq!(wav_data(part).len(), 16000);
+            assert_eq!(wav_duration(part), Some(1.0));
+            // The fmt chunk is copied unchanged
+            assert_eq!(part[12..36], original[12..36]);
+        }
+        let joined: Vec<u8> = parts.iter().flat_map(|p| wav_data(p).to_vec()).collect();
+        assert_eq!(joined, parse_wav(&original).unwrap().data);
+    }
+
+    #[test]
+    fn wav_parts_keep_sample_frames_whole() {
+        // 0.3 s of 16-bit stereo is not a whole number of frames at 44.1 kHz
+        let original = wav(2, 44100, 16, 44100, false);
+        let parts = split_wav(&original, 0.3).unwrap();
+        assert_eq!(parts.len(), 4);
+        let sizes: Vec<usize> = parts.iter().map(|p| wav_data(p).len()).collect();
+        assert!(sizes.iter().all(|size| size % 4 == 0));
+        assert!(sizes[..3].iter().all(|&size| size == sizes[0] && size <= 52920));
+        assert_eq!(sizes.iter().sum::<usize>(), 44100 * 4);
+    }
+
+    #[test]
+    fn wav_shorter_than_max_duration_is_one_part() {
+        let original = wav(1, 8000, 8, 4000, false);
+        let parts = split_wav(&original, 1.0).unwrap();
+        assert_eq!(parts, vec![original]);
+    }
+
+    const MPEG1_LAYER3_128K_44100: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    const MPEG1_LAYER3_128K_44100_PADDED: [u8; 4] = [0xFF, 0xFB, 0x92, 0x00];
+    const MPEG2_LAYER3_64K_22050: [u8; 4] = [0xFF, 0xF3, 0x80, 0x00];
+
+    fn frame(header: [u8; 4], len: usize, fill: u8) -> Vec<u8> {
+        let mut frame = vec![fill; len];
+        frame[..4].copy_from_slice(&header);
+        frame
+    }
+
+    /// MPEG-1 Layer III frames alternating between 417 and 418 bytes
+    fn mp3_frames(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| match i % 2 {
+                0 => frame(MPEG1_LAYER3_128K_44100, 417, i as u8),
+                _ => frame(MPEG1_LAYER3_128K_44100_PADDED, 418, i as u8),
+            })
+            .collect()
+    }
+
+    fn with_tags(frames: &[u8]) -> Vec<u8> {
+        // ID3v2 header with a synchsafe size of 130 (0x01 0x02)
+        let mut mp3 = b"ID3\x04\x00\x00\x00\x00\x01\x02".to_vec();
+        mp3.extend(vec![0xFF; 130]);
+        mp3.extend_from_slice(frames);
+        mp3.extend_from_slice(b"TAG");
+        mp3.extend(vec![0; 125]);
+        mp3
+    }
+
+    #[test]
+    fn mp3_frame_headers() {
+        assert_eq!(mp3_frame_header(&MPEG1_LAYER3_128K_44100), Some((417, 44100, 1152)));
+        assert_eq!(mp3_frame_header(&MPEG1_LAYER3_128K_44100_PADDED), Some((418, 44100, 1152)));
+        assert_eq!(mp3_frame_header(&MPEG2_LAYER3_64K_22050), Some((208, 22050, 576)));
+        // Layer I at 32 kHz: (12 * 32000 / 32000 + 0) * 4
+        assert_eq!(mp3_frame_header(&[0xFF, 0xFF, 0x18, 0x00]), Some((48, 32000, 384)));
+        // Free format and reserved sample rate
+        assert_eq!(mp3_frame_header(&[0xFF, 0xFB, 0x00, 0x00]), None);
+        assert_eq!(mp3_frame_header(&[0xFF, 0xFB, 0x9C, 0x00]), None);
+    }
+
+    #[test]
+    fn mp3_duration_skips_tags() {
+        let frames = mp3_frames(100);
+        let expected = 100.0 * 1152.0 / 44100.0;
+        assert!((duration(&frames).unwrap() - expected).abs() < 1e-4);
+        assert!((duration(&with_tags(&frames)).unwrap() - expected).abs() < 1e-4);
+
+        let mpeg2: Vec<u8> = (0..10).flat_map(|_| frame(MPEG2_LAYER3_64K_22050, 208, 0)).collect();
+        assert!((duration(&mpeg2).unwrap() - 10.0 * 576.0 / 22050.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn mp3_splits_between_frames_at_max_duration() {
+        let frames = mp3_frames(25);
+        let frame_duration = 1152.0 / 44100.0;
+        let parts = split_mp3(&with_tags(&frames), 10.0 * frame_duration).unwrap();
+        let lengths: Vec<usize> = parts.iter().map(Vec::len).collect();
+        assert_eq!(lengths, vec![10 * 417 + 5, 10 * 417 + 5, 5 * 417 + 2]);
+        // The tags are dropped and the frames kept in order
+        assert_eq!(parts.concat(), frames);
+        for part in &parts {
+            assert!(duration(part).unwrap() <= 10.0 * frame_duration + 1e-4);
+        }
+    }
+
+    #[test]
+    fn unknown_audio_cannot_be_measured_or_split() {
+        let m4a = b"\x00\x00\x00\x20ftypM4A \x00\x00\x00\x00M4A mp42isom";
+        assert_eq!(duration(m4a), None);
+        assert_eq!(split(m4a, 1.0), None);
+        assert_eq!(duration(b"ID3\x04\x00\x00\x00\x00\x00\x00not audio"), None);
+    }
+}
@@ -2,15 +2,19 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 
 use crate::{
+    audio,
     cache::DiagramCacheEntry,
-    configs::{model, provider::{self, Provider}},
-	db::types::{Conversation, Message, ThreadTreeItem},
+    configs::{model::{self, FallbackTarget}, provider::{self, Provider}, TitleGeneration},
+	db::types::{Attachment, Compaction, Conversation, ConversationQuery, Folder, Generation, IntegrityReport, Message, MessageRole, Tag, TaggedMessage, ThreadTreeItem, TitleSource},
     inet::HttpClient,
-    types::AppData,
+    knowledge::{self, types::{IndexReport, KnowledgeBaseInfo}},
+    retry::ProviderCallError,
+    scheduler::Priority,
+    types::{AppData, ConversationTitle, FallbackNotice, FanOutResult, ModelTarget},
     utils::compute_content_hash,
 };
 use serde_json::Value;
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Emitter, Manager};
 
 use crate::utils::get_uuid_v4;
 
@@ -224,25 +228,1129 @@ pub async fn configs_delete_model(
     }
 }
 
+#[tauri::command]
+pub async fn configs_get_title_generation(app_handle: AppHandle) -> Result<TitleGeneration, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let state = state.lock().unwrap();
+    Ok(state.config_manager.get_title_generation())
+}
+
+#[tauri::command]
+pub async fn configs_set_title_generation(
+    app_handle: AppHandle,
+    title_generation: TitleGeneration,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let state = state.lock().unwrap();
+    state
+        .config_manager
+        .set_title_generation(title_generation)
+        .map_err(|e| e.to_string())
+}
+
+// Knowledge base
+#[tauri::command]
+pub async fn knowledge_create_base(
+    app_handle: AppHandle,
+    name: String,
+    embedding_provider: String,
+    embedding_model: String,
+) -> Result<String, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    let provider = state
+        .config_manager
+        .get_provider(&embedding_provider)
+        .ok_or_else(|| "Provider not found".to_string())?;
+    match provider.get_model(&embedding_model).map(|m| &m.model_info) {
+        Some(model::ModelInfo::Embedding { .. }) => {}
+        Some(_) => return Err(format!("{} is not an embedding model", embedding_model)),
+        None => return Err("Model not found".to_string()),
+    }
+
+    let id = get_uuid_v4();
+    state
+        .knowledge
+        .store
+        .create_base(&id, &name, &embedding_provider, &embedding_model)
+        .map(|_| id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_list_bases(app_handle: AppHandle) -> Result<Vec<KnowledgeBaseInfo>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state.knowledge.store.list_bases().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_delete_base(app_handle: AppHandle, knowledge_base_id: String) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .store
+        .delete_base(&knowledge_base_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_add_source(
+    app_handle: AppHandle,
+    knowledge_base_id: String,
+    path: String,
+) -> Result<(), String> {
+    if !std::path::Path::new(&path).is_dir() {
+        return Err(format!("{} is not a folder", path));
+    }
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .store
+        .add_source(&knowledge_base_id, &path)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_remove_source(
+    app_handle: AppHandle,
+    knowledge_base_id: String,
+    path: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .store
+        .remove_source(&knowledge_base_id, &path)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_index(
+    app_handle: AppHandle,
+    knowledge_base_id: String,
+) -> Result<IndexReport, String> {
+    let (mut knowledge, provider) = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+        let base = state
+            .knowledge
+            .store
+            .get_base(&knowledge_base_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Knowledge base not found".to_string())?;
+        let provider = state
+            .config_manager
+            .get_provider(&base.embedding_provider)
+            .ok_or_else(|| "Provider not found".to_string())?;
+        (state.knowledge.clone(), provider)
+    };
+
+    knowledge
+        .index(&app_handle, &knowledge_base_id, &provider)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_attach(
+    app_handle: AppHandle,
+    conversation_id: String,
+    knowledge_base_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .store
+        .attach(&conversation_id, &knowledge_base_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_detach(
+    app_handle: AppHandle,
+    conversation_id: String,
+    knowledge_base_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .store
+        .detach(&conversation_id, &knowledge_base_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn knowledge_list_attached(
+    app_handle: AppHandle,
+    conversation_id: String,
+) -> Result<Vec<KnowledgeBaseInfo>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .knowledge
+        .list_attached(&conversation_id)
+        .map_err(|e| e.to_string())
+}
+
 // OpenAI integration
+/// The model to ask first, followed by the fallback chain of the conversation,
+/// or of the model when the conversation has none
+fn fallback_chain(
+    app_handle: &AppHandle,
+    conversation_id: Option<&str>,
+    provider: Provider,
+    model: String,
+) -> Result<Vec<ModelTarget>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    let mut fallbacks = match conversation_id {
+        Some(conversation_id) => state
+            .chat
+            .fallbacks_manager
+            .get(conversation_id)
+            .map_err(|e| e.to_string())?,
+        None => vec![],
+    };
+    if fallbacks.is_empty() {
+        fallbacks = provider
+            .get_model(&model)
+            .map(|m| m.fallbacks.clone())
+            .unwrap_or_default();
+    }
+
+    let mut chain = vec![ModelTarget { provider, model }];
+    for fallback in fallbacks {
+        match state.config_manager.get_provider(&fallback.provider) {
+            Some(provider) => chain.push(ModelTarget { provider, model: fallback.model }),
+            None => log::warn!(
+                "Skipping fallback {}/{}: provider not found",
+                fallback.provider,
+                fallback.model
+            ),
+        }
+    }
+    Ok(chain)
+}
+
+/// Streams a reply with `n` choices. When `parent_id` is given, the history is
+/// read from the stored branch ending at it, and every choice is stored as a
+/// bot message under it and the new message IDs are returned.
+/// Requests that fail before streaming anything move on to the next entry of
+/// the fallback chain; the model that answered is recorded on the messages.
+#[tauri::command]
+pub async fn ask_openai_stream(
+    app_handle: AppHandle,
+    messages: Vec<Value>,
+    model: String,
+	provider: Provider,
+    conversation_id: Option<String>,
+    parent_id: Option<String>,
+    n: Option<u8>,
+) -> Result<Vec<String>, String> {
+    let n = n.unwrap_or(1).max(1);
+    let history = match &parent_id {
+        // Rebuild the history from the stored branch, keeping only the system
+        // prompts of the frontend messages
+        Some(parent_id) => {
+            let path = {
+                let state = app_handle.state::<Mutex<AppData>>();
+                let mut state = state.lock().unwrap();
+                state
+                    .chat
+                    .get_branch_path(parent_id)
+                    .map_err(|e| e.to_string())?
+            };
+            let (mut prompts, _): (Vec<_>, Vec<_>) = crate::api::messages_from_values(messages)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .partition(|m| matches!(m.sender, MessageRole::System));
+            prompts.extend(path);
+            prompts
+        }
+        None => crate::api::messages_from_values(messages).map_err(|e| e.to_string())?,
+    };
+
+    let mut chunks = vec![];
+    if let Some(conversation_id) = &conversation_id {
+        let (mut knowledge, providers) = {
+            let state = app_handle.state::<Mutex<AppData>>();
+            let state = state.lock().unwrap();
+            (state.knowledge.clone(), state.config_manager.get_providers())
+        };
+
+        let query = history
+            .iter()
+            .rev()
+            .find(|m| matches!(m.sender, MessageRole::User))
+            .map(|m| m.text.clone());
+
+        if let Some(query) = query {
+            // A failing knowledge base should not stop the message from being sent
+            chunks = knowledge
+                .retrieve(&app_handle, &providers, conversation_id, &query, knowledge::DEFAULT_TOP_K)
+                .await
+                .unwrap_or_else(|e| {
+                    log::warn!("Knowledge retrieval failed, sending without context: {}", e);
+                    vec![]
+                });
+            if !chunks.is_empty() {
+                let citations: Vec<_> = chunks.iter().map(|c| c.citation.clone()).collect();
+                app_handle
+                    .emit("knowledge_citations", citations)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    let requested = (provider.name.clone(), model.clone());
+    let mut chain = fallback_chain(&app_handle, conversation_id.as_deref(), provider, model)?.into_iter().peekable();
+    let (target, replies) = loop {
+        let Some(target) = chain.next() else {
+            return Err("No model to send the request to".to_string());
+        };
+        let policy = crate::api::reasoning_policy(&target.provider, &target.model);
+        let mut messages = crate::api::chat_request_messages(&history, policy);
+        knowledge::inject_context(&mut messages, &chunks);
+
+        let error = match crate::api::ask_openai_stream(app_handle.clone(), messages, target.model.clone(), target.provider.clone(), n).await {
+            Ok(replies) => break (target, replies),
+            Err(e) => {
+                let allows_fallback = e
+                    .downcast_ref::<ProviderCallError>()
+                    .is_some_and(|e| e.allows_fallback());
+                (e.to_string(), allows_fallback)
+            }
+        };
+        let next = match (error, chain.peek()) {
+            ((reason, true), Some(next)) => FallbackNotice {
+                from_provider: target.provider.name.clone(),
+                from_model: target.model.clone(),
+                to_provider: next.provider.name.clone(),
+                to_model: next.model.clone(),
+                reason,
+            },
+            ((reason, _), _) => return Err(reason),
+        };
+        app_handle
+            .emit("provider_fallback", next)
+            .map_err(|e| e.to_string())?;
+    };
+
+    let (Some(conversation_id), Some(parent_id)) = (conversation_id, parent_id) else {
+        return Ok(vec![]);
+    };
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    let mut message_ids = Vec::with_capacity(replies.len());
+    for (index, reply) in replies.iter().enumerate() {
+        let message_id = get_uuid_v4();
+        let mut parameters = serde_json::json!({ "n": n, "choice_index": index });
+        if (target.provider.name.as_str(), target.model.as_str()) != (requested.0.as_str(), requested.1.as_str()) {
+            parameters["fallback_from"] = serde_json::json!({ "provider": requested.0, "model": requested.1 });
+        }
+        let generation = Generation {
+            message_id: message_id.clone(),
+            provider: target.provider.name.clone(),
+            model: target.model.clone(),
+            parameters: Some(parameters),
+        };
+        let reasoning = Some(reply.reasoning.as_str()).filter(|r| !r.is_empty());
+        state
+            .chat
+            .add_generated_message(
+                &conversation_id,
+                &reply.text,
+                reasoning,
+                Some(&parent_id),
+                &[],
+                &generation,
+            )
+            .map_err(|e| e.to_string())?;
+        message_ids.push(message_id);
+    }
+    Ok(message_ids)
+}
+
+/// Streams a new reply to the parent of `message_id` and stores it as a sibling
+/// of that message, leaving the original in place. Only bot messages can be
+/// regenerated. Returns the new message ID.
+#[tauri::command]
+pub async fn regenerate(
+    app_handle: AppHandle,
+    conversation_id: String,
+    message_id: String,
+    model: String,
+    provider: Provider,
+    system_prompt: Option<String>,
+) -> Result<String, String> {
+    let (parent_id, path) = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+        let message = state
+            .chat
+            .messages_manager
+            .get(&message_id)
+            .map_err(|e| e.to_string())?;
+        if !matches!(message.sender, MessageRole::Assistant) {
+            return Err("Only bot messages can be regenerated".to_string());
+        }
+        let parent_id = state
+            .chat
+            .thread_manager
+            .get_parent(&message_id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "Cannot regenerate a message without a parent".to_string())?;
+        let path = state
+            .chat
+            .get_branch_path(&parent_id)
+            .map_err(|e| e.to_string())?;
+        (parent_id, path)
+    };
+
+    let policy = crate::api::reasoning_policy(&provider, &model);
+    let mut messages = crate::api::chat_request_messages(&path, policy);
+    if let Some(system_prompt) = system_prompt {
+        messages.insert(0, serde_json::json!({ "role": "system", "content": system_prompt }));
+    }
+
+    let reply = crate::api::ask_openai_stream(app_handle.clone(), messages, model.clone(), provider.clone(), 1)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+
+    let new_message_id = get_uuid_v4();
+    let generation = Generation {
+        message_id: new_message_id.clone(),
+        provider: provider.name.clone(),
+        model,
+        parameters: Some(serde_json::json!({ "regenerated_from": message_id })),
+    };
+    let reasoning = Some(reply.reasoning.as_str()).filter(|r| !r.is_empty());
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .add_generated_message(
+            &conversation_id,
+            &reply.text,
+            reasoning,
+            Some(&parent_id),
+            &[],
+            &generation,
+        )
+        .map(|_| new_message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn complete_fim(
+    app_handle: AppHandle,
+    prefix: String,
+    suffix: String,
+    model: String,
+    provider: Provider,
+) -> Result<(), String> {
+    crate::api::complete_fim(app_handle, prefix, suffix, model, provider)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Streams the rest of a partial bot message and appends it to the same row.
+/// The partial text is sent as the trailing assistant turn. Models with the
+/// prefill capability get it marked as a prefix to extend; other models see it
+/// as their last turn.
+#[tauri::command]
+pub async fn continue_message(
+    app_handle: AppHandle,
+    message_id: String,
+    model: String,
+    provider: Provider,
+    system_prompt: Option<String>,
+) -> Result<(), String> {
+    let path = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+        state
+            .chat
+            .get_branch_path(&message_id)
+            .map_err(|e| e.to_string())?
+    };
+    if !matches!(path.last().map(|m| &m.sender), Some(MessageRole::Assistant)) {
+        return Err("Only bot messages can be continued".to_string());
+    }
+
+    let policy = crate::api::reasoning_policy(&provider, &model);
+    let mut messages = crate::api::chat_request_messages(&path, policy);
+    if let Some(system_prompt) = system_prompt {
+        messages.insert(0, serde_json::json!({ "role": "system", "content": system_prompt }));
+    }
+    let supports_prefill = matches!(
+        provider.get_model(&model).map(|m| &m.model_info),
+        Some(model::ModelInfo::TextGeneration { capabilities, .. })
+            if capabilities.contains(&model::TextModelCapability::Prefill)
+    );
+    if supports_prefill {
+        if let Some(partial) = messages.last_mut() {
+            partial["prefix"] = true.into();
+        }
+    }
+
+    let reply = crate::api::ask_openai_stream(app_handle.clone(), messages, model, provider, 1)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .messages_manager
+        .append(&message_id, &reply.text, &reply.reasoning)
+        .map_err(|e| e.to_string())
+}
+
+/// Streams replies to `parent_id` from several models at once and stores each
+/// one as a sibling bot message. A failing model does not stop the others.
+#[tauri::command]
+pub async fn fan_out(
+    app_handle: AppHandle,
+    conversation_id: String,
+    parent_id: String,
+    targets: Vec<ModelTarget>,
+    system_prompt: Option<String>,
+) -> Result<Vec<FanOutResult>, String> {
+    let path = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+        state
+            .chat
+            .get_branch_path(&parent_id)
+            .map_err(|e| e.to_string())?
+    };
+
+    let runs = targets.into_iter().map(|target| {
+        let app_handle = app_handle.clone();
+        let policy = crate::api::reasoning_policy(&target.provider, &target.model);
+        let mut messages = crate::api::chat_request_messages(&path, policy);
+        if let Some(system_prompt) = &system_prompt {
+            messages.insert(0, serde_json::json!({ "role": "system", "content": system_prompt }));
+        }
+        let conversation_id = conversation_id.clone();
+        let parent_id = parent_id.clone();
+        let message_id = get_uuid_v4();
+
+        async move {
+            let reply = crate::api::stream_chat(&app_handle, messages, target.model.clone(), &target.provider, 1, Priority::Interactive, |_, channel, delta| {
+                let event = match channel {
+                    crate::api::StreamChannel::Content => "fanout_stream_chunk",
+                    crate::api::StreamChannel::Reasoning => "fanout_stream_chunk_reasoning",
+                };
+                let chunk = crate::api::TaggedStreamChunk {
+                    message_id: message_id.clone(),
+                    provider: target.provider.name.clone(),
+                    model: target.model.clone(),
+                    delta,
+                };
+                app_handle.emit(event, chunk).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .map(|replies| replies.into_iter().next().unwrap_or_default());
+
+            let error = match reply {
+                Ok(reply) => {
+                    let generation = Generation {
+                        message_id: message_id.clone(),
+                        provider: target.provider.name.clone(),
+                        model: target.model.clone(),
+                        parameters: None,
+                    };
+                    let reasoning = Some(reply.reasoning.as_str()).filter(|r| !r.is_empty());
+                    let state = app_handle.state::<Mutex<AppData>>();
+                    let mut state = state.lock().unwrap();
+                    state
+                        .chat
+                        .add_generated_message(
+                            &conversation_id,
+                            &reply.text,
+                            reasoning,
+                            Some(&parent_id),
+                            &[],
+                            &generation,
+                        )
+                        .err()
+                        .map(|e| e.to_string())
+                }
+                Err(e) => Some(e),
+            };
+
+            let result = FanOutResult {
+                message_id,
+                provider: target.provider.name,
+                model: target.model,
+                error,
+            };
+            let _ = app_handle.emit("fanout_stream_done", result.clone());
+            result
+        }
+    });
+
+    Ok(futures::future::join_all(runs).await)
+}
+
+// Image generation
+#[tauri::command]
+pub async fn generate_image(
+    app_handle: AppHandle,
+    conversation_id: String,
+    parent_id: Option<String>,
+    prompt: String,
+    model: String,
+    provider: Provider,
+) -> Result<String, String> {
+    let params = match provider.get_model(&model).map(|m| &m.model_info) {
+        Some(model::ModelInfo::ImageGeneration { parameters }) => parameters.clone(),
+        Some(_) => return Err(format!("{} is not an image generation model", model)),
+        None => return Err("Model not found".to_string()),
+    };
+
+    let body = crate::api::image_generation_body(&model, &prompt, &params);
+    let images = crate::api::generate_images(&app_handle, &provider, &body)
+        .await
+        .map_err(|e| e.to_string())?;
+    if images.is_empty() {
+        return Err("No image was generated".to_string());
+    }
+
+    let message_id = get_uuid_v4();
+    let text = images
+        .iter()
+        .find_map(|i| i.revised_prompt.clone())
+        .unwrap_or_default();
+    let attachments: Vec<Attachment> = images
+        .into_iter()
+        .enumerate()
+        .map(|(i, image)| Attachment {
+            id: get_uuid_v4(),
+            message_id: message_id.clone(),
+            name: format!(
+                "image-{}.{}",
+                i + 1,
+                image.mime_type.rsplit('/').next().unwrap_or("bin")
+            ),
+            mime_type: image.mime_type,
+            data: image.data,
+        })
+        .collect();
+    let generation = Generation {
+        message_id: message_id.clone(),
+        provider: provider.name.clone(),
+        model,
+        parameters: Some(body),
+    };
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .add_generated_message(
+            &conversation_id,
+            &text,
+            None,
+            parent_id.as_deref(),
+            &attachments,
+            &generation,
+        )
+        .map(|_| message_id)
+        .map_err(|e| e.to_string())
+}
+
+// Audio
+#[tauri::command]
+pub async fn transcribe_audio(
+    app_handle: AppHandle,
+    conversation_id: String,
+    parent_id: Option<String>,
+    path: String,
+    model: String,
+    provider: Provider,
+) -> Result<String, String> {
+    let (params, support) = match provider.get_model(&model).map(|m| &m.model_info) {
+        Some(model::ModelInfo::Audio { parameters, support }) => (parameters.clone(), support.clone()),
+        Some(_) => return Err(format!("{} is not an audio model", model)),
+        None => return Err("Model not found".to_string()),
+    };
+
+    let file_path = std::path::Path::new(&path);
+    let data = std::fs::read(file_path).map_err(|e| e.to_string())?;
+    let file_name = file_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("audio")
+        .to_string();
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+
+    // Only WAV and MP3 files can be measured and cut here, so other formats
+    // are refused when the model has a length limit
+    let split_parts = match support.max_duration {
+        Some(max_duration) => match audio::duration(&data) {
+            Some(duration) if duration > max_duration => Some(
+                audio::split(&data, max_duration)
+                    .ok_or_else(|| format!("Could not split {} into parts", file_name))?,
+            ),
+            Some(_) => None,
+            None => {
+                return Err(format!(
+                    "{} only accepts audio up to {} seconds, and the length of {} cannot be checked. Convert it to WAV or MP3 so it can be split.",
+                    model, max_duration, file_name
+                ))
+            }
+        },
+        None => None,
+    };
+    let parts: Vec<&[u8]> = match &split_parts {
+        Some(parts) => parts.iter().map(Vec::as_slice).collect(),
+        None => vec![&data],
+    };
+
+    let mut transcripts = Vec::with_capacity(parts.len());
+    for part in parts {
+        let text = crate::api::transcribe_audio(&app_handle, &provider, &model, &file_name, part, params.language.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        transcripts.push(text.trim().to_string());
+    }
+    let text = transcripts.join(" ");
+
+    let message_id = get_uuid_v4();
+    let attachment = Attachment {
+        id: get_uuid_v4(),
+        message_id: message_id.clone(),
+        name: file_name,
+        mime_type: audio::mime_type_for_extension(extension).to_string(),
+        data,
+    };
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .add_message_with_attachments(
+            &conversation_id,
+            &message_id,
+            &text,
+            &MessageRole::User.to_string(),
+            parent_id.as_deref(),
+            &[attachment],
+        )
+        .map(|_| message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn synthesize_speech(
+    app_handle: AppHandle,
+    message_id: String,
+    model: String,
+    provider: Provider,
+) -> Result<String, String> {
+    let params = match provider.get_model(&model).map(|m| &m.model_info) {
+        Some(model::ModelInfo::Audio { parameters, .. }) => parameters.clone(),
+        Some(_) => return Err(format!("{} is not an audio model", model)),
+        None => return Err("Model not found".to_string()),
+    };
+
+    let text = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+        state
+            .chat
+            .messages_manager
+            .get(&message_id)
+            .map_err(|e| e.to_string())?
+            .text
+    };
+
+    let body = crate::api::speech_body(&model, &text, &params);
+    let data = crate::api::synthesize_speech(&app_handle, &provider, &body)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let format = body["response_format"].as_str().unwrap_or("mp3");
+    let attachment = Attachment {
+        id: get_uuid_v4(),
+        message_id,
+        name: format!("speech.{}", format),
+        mime_type: audio::mime_type_for_extension(format).to_string(),
+        data,
+    };
+
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .attachments_manager
+        .add(&attachment)
+        .map(|_| attachment.id.clone())
+        .map_err(|e| e.to_string())
+}
+
+// Chat operations
+#[tauri::command]
+pub async fn create_conversation(
+    app_handle: AppHandle,
+    name: String,
+    description: String,
+) -> Result<String, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    let id = get_uuid_v4();
+    state
+        .chat
+        .create_conversation(&id, &name, &description)
+        .map(|_| id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn add_message(
+    app_handle: AppHandle,
+    conversation_id: String,
+    text: String,
+    reasoning: Option<String>,
+    sender: String,
+    parent_id: Option<String>,
+) -> Result<String, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    let message_id = get_uuid_v4();
+    state
+        .chat
+        .add_message(
+            &conversation_id,
+            &message_id,
+            &text,
+            reasoning.as_deref(),
+            &sender,
+            parent_id.as_deref(),
+        )
+        .map(|_| message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_message(
+    app_handle: AppHandle,
+    message_id: String,
+    text: String,
+    reasoning: Option<String>,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .update_message(&message_id, &text)
+        .map_err(|e| e.to_string())?;
+    if let Some(reasoning) = reasoning {
+        let _ = state
+            .chat
+            .messages_manager
+            .update_reasoning(&message_id, &reasoning);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_message(app_handle: AppHandle, message_id: String) -> Result<Message, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .get_message(&message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_message_attachments(
+    app_handle: AppHandle,
+    message_id: String,
+) -> Result<Vec<Attachment>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .attachments_manager
+        .get_by_message(&message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_message_generation(
+    app_handle: AppHandle,
+    message_id: String,
+) -> Result<Option<Generation>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .generations_manager
+        .get(&message_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_message(
+    app_handle: AppHandle,
+    message_id: String,
+    recursive: bool,
+) -> Result<Option<String>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .delete_message(&message_id, recursive)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_all_message_involved(
+    app_handle: AppHandle,
+    conversation_id: String,
+) -> Result<Vec<Message>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .get_all_message_involved(&conversation_id)
+        .map_err(|e| e.to_string())
+}
+
+/// The messages on the path from the conversation root down to
+/// `leaf_message_id`, in order
+#[tauri::command]
+pub async fn get_branch_path(
+    app_handle: AppHandle,
+    leaf_message_id: String,
+) -> Result<Vec<Message>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .get_branch_path(&leaf_message_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Remembers the branch ending at `message_id` as the one the user is on, so
+/// it is restored when the conversation is reopened
+#[tauri::command]
+pub async fn set_active_leaf(
+    app_handle: AppHandle,
+    conversation_id: String,
+    message_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .set_active_leaf(&conversation_id, &message_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Moves a message and its replies under `new_parent_id`, or to the root of
+/// the conversation when no parent is given
+#[tauri::command]
+pub async fn move_subtree(
+    app_handle: AppHandle,
+    message_id: String,
+    new_parent_id: Option<String>,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .move_subtree(&message_id, new_parent_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Copies the branch ending at `message_id`, and the replies below it when
+/// `include_subtree` is set, into a new conversation. Returns its ID.
+#[tauri::command]
+pub async fn fork_conversation(
+    app_handle: AppHandle,
+    message_id: String,
+    name: String,
+    include_subtree: Option<bool>,
+) -> Result<String, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    let id = get_uuid_v4();
+    state
+        .chat
+        .fork_conversation(&id, &message_id, &name, include_subtree.unwrap_or(false))
+        .map(|_| id)
+        .map_err(|e| e.to_string())
+}
+
+/// Reports messages with several parents, a missing parent or no
+/// conversation. With `repair`, extra parents are dropped, messages with a
+/// missing parent are re-attached to their conversation, and only messages
+/// that belong to no conversation are deleted.
+#[tauri::command]
+pub async fn check_thread_integrity(app_handle: AppHandle, repair: bool) -> Result<IntegrityReport, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .check_integrity(repair)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_thread_tree(
+    app_handle: AppHandle,
+    conversation_id: String,
+) -> Result<Vec<ThreadTreeItem>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .get_thread_tree(&conversation_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_conversation_entry_id(
+    app_handle: AppHandle,
+    conversation_id: String,
+    message_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .conversation_manager
+        .update_entry_message_id(&conversation_id, Some(&message_id))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_conversation(
+    app_handle: AppHandle,
+    conversation_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .delete_conversation(&conversation_id)
+        .map_err(|e| e.to_string())?;
+    state
+        .knowledge
+        .store
+        .detach_conversation(&conversation_id)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
-pub async fn ask_openai_stream(
+pub async fn update_conversation(
     app_handle: AppHandle,
-    messages: Vec<Value>,
-    model: String,
-	provider: Provider,
+    conversation_id: String,
+    name: Option<String>,
+    description: Option<String>,
+    pinned: Option<bool>,
+    archived: Option<bool>,
 ) -> Result<(), String> {
-    crate::api::ask_openai_stream(app_handle, messages, model, provider)
-        .await
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    if let Some(name) = name {
+        state
+            .chat
+            .conversation_manager
+            .update_name(&conversation_id, &name)
+            .map_err(|e| e.to_string())?;
+        state
+            .chat
+            .titles_manager
+            .set_source(&conversation_id, TitleSource::User)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if let Some(description) = description {
+        state
+            .chat
+            .conversation_manager
+            .update_description(&conversation_id, &description)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if let Some(pinned) = pinned {
+        state
+            .chat
+            .conversation_manager
+            .update_pinned(&conversation_id, pinned)
+            .map_err(|e| e.to_string())?;
+    }
+
+    if let Some(archived) = archived {
+        state
+            .chat
+            .conversation_manager
+            .update_archived(&conversation_id, archived)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Moves a conversation into a folder, or out of any folder without one
+#[tauri::command]
+pub async fn move_conversation_to_folder(
+    app_handle: AppHandle,
+    conversation_id: String,
+    folder_id: Option<String>,
+) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .move_conversation_to_folder(&conversation_id, folder_id.as_deref())
         .map_err(|e| e.to_string())
 }
 
-// Chat operations
+/// Creates a folder, at the top level without a parent. Returns its ID.
 #[tauri::command]
-pub async fn create_conversation(
+pub async fn create_folder(
     app_handle: AppHandle,
     name: String,
-    description: String,
+    parent_id: Option<String>,
 ) -> Result<String, String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
@@ -250,174 +1358,402 @@ pub async fn create_conversation(
     let id = get_uuid_v4();
     state
         .chat
-        .create_conversation(&id, &name, &description)
+        .folders_manager
+        .create(&id, &name, parent_id.as_deref())
         .map(|_| id)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn add_message(
-    app_handle: AppHandle,
-    conversation_id: String,
-    text: String,
-    reasoning: Option<String>,
-    sender: String,
-    parent_id: Option<String>,
-) -> Result<String, String> {
+pub async fn rename_folder(app_handle: AppHandle, folder_id: String, name: String) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
 
-    let message_id = get_uuid_v4();
     state
         .chat
-        .add_message(
-            &conversation_id,
-            &message_id,
-            &text,
-            reasoning.as_deref(),
-            &sender,
-            parent_id.as_deref(),
-        )
-        .map(|_| message_id)
+        .folders_manager
+        .rename(&folder_id, &name)
         .map_err(|e| e.to_string())
 }
 
+/// Moves a folder under another one, or to the top level without a parent
 #[tauri::command]
-pub async fn update_message(
+pub async fn move_folder(
     app_handle: AppHandle,
-    message_id: String,
-    text: String,
-    reasoning: Option<String>,
+    folder_id: String,
+    parent_id: Option<String>,
 ) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
+
     state
         .chat
-        .update_message(&message_id, &text)
-        .map_err(|e| e.to_string())?;
-    if let Some(reasoning) = reasoning {
-        let _ = state
-            .chat
-            .messages_manager
-            .update_reasoning(&message_id, &reasoning);
-    }
-    Ok(())
+        .folders_manager
+        .update_parent(&folder_id, parent_id.as_deref())
+        .map_err(|e| e.to_string())
 }
 
+/// Deletes a folder. Its subfolders and conversations move to its parent.
 #[tauri::command]
-pub async fn get_message(app_handle: AppHandle, message_id: String) -> Result<Message, String> {
+pub async fn delete_folder(app_handle: AppHandle, folder_id: String) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
+
     state
         .chat
-        .messages_manager
-        .get(&message_id)
+        .folders_manager
+        .delete(&folder_id)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn delete_message(
-    app_handle: AppHandle,
-    message_id: String,
-    recursive: bool,
-) -> Result<Option<String>, String> {
+pub async fn list_folders(app_handle: AppHandle) -> Result<Vec<Folder>, String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
+
     state
         .chat
-        .delete_message(&message_id, recursive)
+        .folders_manager
+        .list()
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_all_message_involved(
-    app_handle: AppHandle,
-    conversation_id: String,
-) -> Result<Vec<Message>, String> {
+pub async fn list_tags(app_handle: AppHandle) -> Result<Vec<Tag>, String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
 
     state
         .chat
-        .get_all_message_involved(&conversation_id)
+        .tags_manager
+        .list()
         .map_err(|e| e.to_string())
 }
 
+/// Deletes a tag and removes it from every conversation and message
 #[tauri::command]
-pub async fn get_thread_tree(
+pub async fn delete_tag(app_handle: AppHandle, tag_id: String) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tags_manager
+        .delete(&tag_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Tags a conversation by tag name, creating the tag if needed
+#[tauri::command]
+pub async fn add_conversation_tag(
     app_handle: AppHandle,
     conversation_id: String,
-) -> Result<Vec<ThreadTreeItem>, String> {
+    name: String,
+) -> Result<Tag, String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
 
     state
         .chat
-        .get_thread_tree(&conversation_id)
+        .tag_conversation(&conversation_id, &name)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn update_conversation_entry_id(
+pub async fn remove_conversation_tag(
     app_handle: AppHandle,
     conversation_id: String,
-    message_id: String,
+    tag_id: String,
 ) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
+
     state
         .chat
-        .conversation_manager
-        .update_entry_message_id(&conversation_id, Some(&message_id))
+        .tags_manager
+        .remove_from_conversation(&conversation_id, &tag_id)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn delete_conversation(
+pub async fn get_conversation_tags(app_handle: AppHandle, conversation_id: String) -> Result<Vec<Tag>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tags_manager
+        .list_by_conversation(&conversation_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Tags a message by tag name, creating the tag if needed
+#[tauri::command]
+pub async fn add_message_tag(app_handle: AppHandle, message_id: String, name: String) -> Result<Tag, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tag_message(&message_id, &name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_message_tag(app_handle: AppHandle, message_id: String, tag_id: String) -> Result<(), String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tags_manager
+        .remove_from_message(&message_id, &tag_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_message_tags(app_handle: AppHandle, message_id: String) -> Result<Vec<Tag>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tags_manager
+        .list_by_message(&message_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Messages carrying a tag, or any tag without one, across all conversations
+#[tauri::command]
+pub async fn list_tagged_messages(app_handle: AppHandle, tag_id: Option<String>) -> Result<Vec<TaggedMessage>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .tags_manager
+        .list_tagged_messages(tag_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Lists conversations, most recently changed first unless `query` says
+/// otherwise
+#[tauri::command]
+pub async fn list_conversations(app_handle: AppHandle, query: Option<ConversationQuery>) -> Result<Vec<Conversation>, String> {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+
+    state
+        .chat
+        .list_conversations(&query.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_conversation_fallbacks(
     app_handle: AppHandle,
     conversation_id: String,
-) -> Result<(), String> {
+) -> Result<Vec<FallbackTarget>, String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
 
     state
         .chat
-        .delete_conversation(&conversation_id)
+        .fallbacks_manager
+        .get(&conversation_id)
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn update_conversation(
+pub async fn set_conversation_fallbacks(
     app_handle: AppHandle,
     conversation_id: String,
-    name: Option<String>,
-    description: Option<String>,
+    fallbacks: Vec<FallbackTarget>,
 ) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
 
-    if let Some(name) = name {
-        state
+    state
+        .chat
+        .fallbacks_manager
+        .set(&conversation_id, &fallbacks)
+        .map_err(|e| e.to_string())
+}
+
+/// Cleans up a title written by a model: first line only, without quotes,
+/// Markdown markers or a trailing full stop
+fn clean_title(text: &str) -> String {
+    let line = text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).unwrap_or_default();
+    let line = line.trim_start_matches(['#', '*', ' ']).trim_end_matches(['*', ' ']);
+    let line = line.trim_matches(['"', '\'', '`']).trim_end_matches('.');
+    line.chars().take(80).collect::<String>().trim().to_string()
+}
+
+/// Names a conversation after its first exchange with the model configured for
+/// titles. Does nothing when titles are disabled, the conversation was already
+/// titled, or the user named it. Returns the new title.
+#[tauri::command]
+pub async fn generate_title(app_handle: AppHandle, conversation_id: String) -> Result<Option<String>, String> {
+    let (settings, provider, exchange) = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
+
+        let settings = state.config_manager.get_title_generation();
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let provider = state
+            .config_manager
+            .get_provider(&settings.provider)
+            .ok_or_else(|| format!("Title provider {} not found", settings.provider))?;
+        if state
             .chat
-            .conversation_manager
-            .update_name(&conversation_id, &name)
+            .titles_manager
+            .get_source(&conversation_id)
+            .map_err(|e| e.to_string())?
+            .is_some()
+        {
+            return Ok(None);
+        }
+
+        let messages = state
+            .chat
+            .get_all_message_involved(&conversation_id)
             .map_err(|e| e.to_string())?;
+        let question = messages.iter().find(|m| matches!(m.sender, MessageRole::User));
+        let answer = messages
+            .iter()
+            .find(|m| matches!(m.sender, MessageRole::Assistant) && !m.text.trim().is_empty());
+        let (Some(question), Some(answer)) = (question, answer) else {
+            return Ok(None);
+        };
+        let exchange = format!(
+            "User: {}\n\nAssistant: {}",
+            question.text.chars().take(2000).collect::<String>(),
+            answer.text.chars().take(2000).collect::<String>()
+        );
+        (settings, provider, exchange)
+    };
+
+    let messages = vec![
+        serde_json::json!({
+            "role": "system",
+            "content": "Write a short title of at most six words for the conversation below. Reply with the title only, in the language of the conversation."
+        }),
+        serde_json::json!({ "role": "user", "content": exchange }),
+    ];
+    let reply = crate::api::stream_chat(&app_handle, messages, settings.model, &provider, 1, Priority::Background, |_, _, _| Ok(()))
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+
+    let title = clean_title(&reply.text);
+    if title.is_empty() {
+        return Ok(None);
     }
 
-    if let Some(description) = description {
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    // The user may have renamed the conversation while the title was generated
+    if state
+        .chat
+        .titles_manager
+        .get_source(&conversation_id)
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {
+        return Ok(None);
+    }
+    state
+        .chat
+        .conversation_manager
+        .update_name(&conversation_id, &title)
+        .map_err(|e| e.to_string())?;
+    state
+        .chat
+        .titles_manager
+        .set_source(&conversation_id, TitleSource::Generated)
+        .map_err(|e| e.to_string())?;
+
+    app_handle
+        .emit(
+            "conversation_title",
+            ConversationTitle {
+                conversation_id,
+                name: title.clone(),
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(Some(title))
+}
+
+/// Summarises the branch above `message_id` with `model` and records the
+/// summary as a compaction point on that message. Later requests through it
+/// send the summary instead of the messages above, which are kept.
+#[tauri::command]
+pub async fn compact_conversation(
+    app_handle: AppHandle,
+    message_id: String,
+    model: String,
+    provider: Provider,
+) -> Result<Compaction, String> {
+    let path = {
+        let state = app_handle.state::<Mutex<AppData>>();
+        let mut state = state.lock().unwrap();
         state
             .chat
-            .conversation_manager
-            .update_description(&conversation_id, &description)
-            .map_err(|e| e.to_string())?;
+            .get_branch_path(&message_id)
+            .map_err(|e| e.to_string())?
+    };
+    let above = &path[..path.len() - 1];
+    if !above.iter().any(|m| !matches!(m.sender, MessageRole::System)) {
+        return Err("There are no messages above this one to compact".to_string());
     }
-    Ok(())
+
+    // Earlier compaction points are honoured, so their summaries are folded in
+    let mut messages = crate::api::chat_request_messages(above, model::ReasoningPolicy::Drop);
+    messages.push(serde_json::json!({
+        "role": "user",
+        "content": "Summarize the conversation so far so that it can replace it as context for the rest of the conversation. Keep every fact, decision, open question and piece of code that may matter later. Reply with the summary only, in the language of the conversation."
+    }));
+    let reply = crate::api::stream_chat(&app_handle, messages, model.clone(), &provider, 1, Priority::Interactive, |_, _, _| Ok(()))
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let summary = reply.text.trim();
+    if summary.is_empty() {
+        return Err("The model returned an empty summary".to_string());
+    }
+
+    let compaction = Compaction {
+        message_id,
+        summary: summary.to_string(),
+        provider: provider.name,
+        model,
+    };
+    let state = app_handle.state::<Mutex<AppData>>();
+    let mut state = state.lock().unwrap();
+    state
+        .chat
+        .compactions_manager
+        .set(&compaction)
+        .map_err(|e| e.to_string())?;
+    Ok(compaction)
 }
 
+/// Removes the compaction point on a message, so requests send the full
+/// history above it again
 #[tauri::command]
-pub async fn list_conversations(app_handle: AppHandle) -> Result<Vec<Conversation>, String> {
+pub async fn delete_compaction(app_handle: AppHandle, message_id: String) -> Result<(), String> {
     let state = app_handle.state::<Mutex<AppData>>();
     let mut state = state.lock().unwrap();
-
-    state.chat.list_conversations().map_err(|e| e.to_string())
+    state
+        .chat
+        .compactions_manager
+        .delete(&message_id)
+        .map_err(|e| e.to_string())
 }
@@ -12,6 +12,16 @@ use thiserror::Error;
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct Config {
 	providers: Vec<provider::Provider>,
+	#[serde(default)]
+	title_generation: TitleGeneration,
+}
+
+/// Naming new conversations with a model after their first exchange
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TitleGeneration {
+	pub enabled: bool,
+	pub provider: String,
+	pub model: String,
 }
 
 #[derive(Debug, Error)]
@@ -80,6 +90,19 @@ impl ConfigManager {
         self.configs.lock().unwrap().providers.clone()
     }
 
+	/// Get the settings of automatic conversation titles.
+	pub fn get_title_generation(&self) -> TitleGeneration {
+		self.configs.lock().unwrap().title_generation.clone()
+	}
+
+	/// Replace the settings of automatic conversation titles.
+	pub fn set_title_generation(&self, title_generation: TitleGeneration) -> Result<(), ConfigError> {
+		let mut configs = self.configs.lock().unwrap();
+		configs.title_generation = title_generation;
+		std::mem::drop(configs);
+		self.save()
+	}
+
 	/// Save the current config to the file.
 	///
 	/// MUST UNLOCK THE MUTEX configs BEFORE CALLING THIS METHOD
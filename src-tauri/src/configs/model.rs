@@ -6,6 +6,21 @@ pub enum TextModelCapability {
     FIM,
     ToolUse,
     Reasoning,
+    /// Extends a trailing assistant message marked with `"prefix": true`
+    /// instead of answering it
+    Prefill,
+}
+
+/// How the reasoning of earlier bot messages is sent back to the model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum ReasoningPolicy {
+    /// Leave previous reasoning out of the request
+    #[default]
+    Drop,
+    /// Send it in the `reasoning_content` field of the assistant message
+    Keep,
+    /// Send it as a `thinking` content block before the reply text
+    ThinkingBlocks,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,6 +69,14 @@ pub struct RerankerParams {
     pub score_threshold: Option<f32>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AudioParams {
+    pub voice: Option<String>,
+    pub response_format: Option<String>,
+    pub speed: Option<f32>,
+    pub language: Option<String>,
+}
+
 // ========== MULTIMODAL SUPPORT ==========
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct VisionSupport {
@@ -89,6 +112,11 @@ pub enum ModelInfo {
         parameters: TextGenerationParams,
         capabilities: Vec<TextModelCapability>,
         multimodal: Option<MultimodalConfig>,
+        #[serde(default)]
+        reasoning_policy: ReasoningPolicy,
+        /// Split `<think>...</think>` in the streamed content into reasoning
+        #[serde(default)]
+        parse_think_tags: bool,
     },
 
     #[serde(rename = "image_generation")]
@@ -108,7 +136,10 @@ pub enum ModelInfo {
 
     #[serde(rename = "audio")]
     Audio {
-        // Audio-specific config
+        #[serde(default)]
+        parameters: AudioParams,
+        #[serde(default)]
+        support: AudioSupport,
     },
 }
 
@@ -120,6 +151,16 @@ pub struct Model {
     pub tokenizer: Option<String>,
     pub max_input_size: usize,
     pub api_endpoint: Option<String>,
+    /// Tried in order when a request to this model fails
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackTarget>,
+}
+
+/// A (provider, model) pair to fall back to, referenced by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FallbackTarget {
+    pub provider: String,
+    pub model: String,
 }
 
 // impl Model {
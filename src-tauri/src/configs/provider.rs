@@ -9,6 +9,41 @@ pub struct Provider {
     pub display_name: String,
     pub base_url: String,
     pub models: Vec<Model>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub limits: ProviderLimits,
+}
+
+/// Limits the scheduler keeps requests to a provider within; unset means unlimited
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProviderLimits {
+    pub max_concurrent: Option<u32>,
+    pub requests_per_minute: Option<u32>,
+    /// Counted from an estimate of the prompt size plus the reply limit
+    pub tokens_per_minute: Option<u32>,
+}
+
+/// How failed requests to a provider are retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -0,0 +1,110 @@
+use rusqlite::{params, Connection};
+use super::DbPool;
+use super::types::{Attachment, MessageError};
+
+pub struct Attachments {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Attachments {
+    pub const TABLE_NAME: &'static str = "attachments";
+
+    pub fn new(pool: DbPool, message_table_name: &str) -> Result<Self, MessageError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+					id TEXT PRIMARY KEY,
+					message_id TEXT NOT NULL,
+					name TEXT NOT NULL,
+					mime_type TEXT NOT NULL,
+					data BLOB NOT NULL,
+					FOREIGN KEY (message_id) REFERENCES {} (id) ON DELETE CASCADE
+				)",
+                Self::TABLE_NAME,
+                message_table_name
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn add(&mut self, attachment: &Attachment) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        Self::add_on(&conn, attachment)
+    }
+
+    /// `add` on a given connection, so it can be part of a transaction.
+    pub fn add_on(conn: &Connection, attachment: &Attachment) -> Result<(), MessageError> {
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (id, message_id, name, mime_type, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+                Self::TABLE_NAME
+            ),
+            params![
+                attachment.id,
+                attachment.message_id,
+                attachment.name,
+                attachment.mime_type,
+                attachment.data
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Get all attachments of a message.
+    pub fn get_by_message(&mut self, message_id: &str) -> Result<Vec<Attachment>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, message_id, name, mime_type, data FROM {} WHERE message_id = ?1 ORDER BY rowid ASC",
+            Self::TABLE_NAME
+        ))?;
+
+        let attachments = stmt
+            .query_map(params![message_id], |row| {
+                Ok(Attachment {
+                    id: row.get(0)?,
+                    message_id: row.get(1)?,
+                    name: row.get(2)?,
+                    mime_type: row.get(3)?,
+                    data: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(attachments)
+    }
+
+    /// Get the attachments of several messages at once.
+    pub fn get_by_messages(&mut self, message_ids: &[&str]) -> Result<Vec<Attachment>, MessageError> {
+        let ids = serde_json::to_string(message_ids).unwrap_or_else(|_| "[]".to_string());
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, message_id, name, mime_type, data FROM {} WHERE message_id IN (SELECT value FROM json_each(?1)) ORDER BY rowid ASC",
+            Self::TABLE_NAME
+        ))?;
+
+        let attachments = stmt
+            .query_map(params![ids], |row| {
+                Ok(Attachment {
+                    id: row.get(0)?,
+                    message_id: row.get(1)?,
+                    name: row.get(2)?,
+                    mime_type: row.get(3)?,
+                    data: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(attachments)
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Self::TABLE_NAME),
+            params![id],
+        )?;
+        Ok(())
+    }
+}
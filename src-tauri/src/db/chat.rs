@@ -1,9 +1,24 @@
+use super::attachments::Attachments;
+use super::compactions::Compactions;
 use super::conversations::Conversations;
+use super::fallbacks::Fallbacks;
+use super::folders::Folders;
+use super::generations::Generations;
+use super::integrity;
 use super::messages::Messages;
+use super::migrations::Migrator;
+use super::tags::Tags;
 use super::threads::Threads;
-use super::types::{ChatError, Conversation, ConversationError, Message, ThreadTreeItem};
+use super::titles::Titles;
+use super::types::{
+    Attachment, ChatError, Compaction, Conversation, ConversationError, ConversationQuery,
+    Generation, IntegrityReport, Message, MessageRole, Tag, ThreadTreeItem,
+};
 use super::{create_pool, DbPool};
-use std::path::PathBuf;
+use rusqlite::Connection;
+use crate::utils::get_uuid_v4;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 
 pub struct Chat {
@@ -11,6 +26,13 @@ pub struct Chat {
     pub thread_manager: Threads,
     pub conversation_manager: Conversations,
     pub messages_manager: Messages,
+    pub attachments_manager: Attachments,
+    pub generations_manager: Generations,
+    pub fallbacks_manager: Fallbacks,
+    pub titles_manager: Titles,
+    pub compactions_manager: Compactions,
+    pub folders_manager: Folders,
+    pub tags_manager: Tags,
 }
 
 #[allow(unused)]
@@ -24,17 +46,37 @@ impl Chat {
         std::fs::create_dir_all(&app_dir).expect("Failed to create app data dir");
         let db_path = PathBuf::from(app_dir).join("messages.db");
         let db_path = db_path.to_str().expect("Failed to reach database path");
+        Self::open(db_path)
+    }
 
+    /// Opens the database at `db_path`, creating and migrating its tables
+    pub fn open(db_path: &str) -> Result<Self, ChatError> {
         let pool = create_pool(db_path);
+        let migrator = Migrator::open(pool.clone(), Path::new(db_path))?;
         let messages_manager = Messages::new(pool.clone())?;
         let thread_manager = Threads::new(pool.clone(), "messages", "id")?;
         let conversation_manager = Conversations::new(pool.clone(), "messages")?;
+        let attachments_manager = Attachments::new(pool.clone(), "messages")?;
+        let generations_manager = Generations::new(pool.clone(), "messages")?;
+        let fallbacks_manager = Fallbacks::new(pool.clone(), Conversations::TABLE_NAME)?;
+        let titles_manager = Titles::new(pool.clone(), Conversations::TABLE_NAME)?;
+        let compactions_manager = Compactions::new(pool.clone(), "messages")?;
+        let folders_manager = Folders::new(pool.clone())?;
+        let tags_manager = Tags::new(pool.clone(), Conversations::TABLE_NAME, "messages")?;
+        migrator.migrate()?;
 
         Ok(Chat {
             pool,
             thread_manager,
             conversation_manager,
             messages_manager,
+            attachments_manager,
+            generations_manager,
+            fallbacks_manager,
+            titles_manager,
+            compactions_manager,
+            folders_manager,
+            tags_manager,
         })
     }
 
@@ -67,99 +109,363 @@ impl Chat {
     ) -> Result<(), ChatError> {
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
+        Self::add_message_on(&tx, conversation_id, message_id, text, reasoning, sender, parent_message_id)?;
+        tx.commit()?;
+        Ok(())
+    }
 
-        // Add the message
-        self.messages_manager
-            .add(message_id, text, reasoning, sender, None, None)?;
+    /// Writes a message, its thread relation and, for a root message, the
+    /// conversation's entry on one connection
+    fn add_message_on(
+        conn: &Connection,
+        conversation_id: &str,
+        message_id: &str,
+        text: &str,
+        reasoning: Option<&str>,
+        sender: &str,
+        parent_message_id: Option<&str>,
+    ) -> Result<(), ChatError> {
+        if !Conversations::exists_on(conn, conversation_id)? {
+            return Err(ChatError::Conversation(ConversationError::Database(
+                rusqlite::Error::QueryReturnedNoRows,
+            )));
+        }
 
-        // Link to parent message
-        self.thread_manager.add(message_id, parent_message_id)?;
+        Messages::add_on(conn, conversation_id, message_id, text, reasoning, sender, None, None)?;
+        Threads::add_on(conn, message_id, parent_message_id)?;
 
         // Link to conversation's entry message if no parent specified
         if parent_message_id.is_none() {
-            let conv =
-                self.conversation_manager
-                    .get(conversation_id)?
-                    .ok_or(ChatError::Conversation(ConversationError::Database(
-                        rusqlite::Error::QueryReturnedNoRows,
-                    )))?;
-            let conv_id: &str = &conv.id;
-            self.conversation_manager
-                .update_entry_message_id(conv_id, Some(message_id))?;
+            Conversations::update_entry_message_id_on(conn, conversation_id, Some(message_id))?;
         }
+        Ok(())
+    }
 
+    /// Adds a message together with its attachments
+    pub fn add_message_with_attachments(
+        &mut self,
+        conversation_id: &str,
+        message_id: &str,
+        text: &str,
+        sender: &str,
+        parent_message_id: Option<&str>,
+        attachments: &[Attachment],
+    ) -> Result<(), ChatError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        Self::add_message_on(&tx, conversation_id, message_id, text, None, sender, parent_message_id)?;
+        for attachment in attachments {
+            Attachments::add_on(&tx, attachment)?;
+        }
         tx.commit()?;
         Ok(())
     }
 
-    /// Gets full message thread for a conversation
+    /// Adds a bot message produced by a model, with its attachments and a record
+    /// of the provider, model and parameters used
+    pub fn add_generated_message(
+        &mut self,
+        conversation_id: &str,
+        text: &str,
+        reasoning: Option<&str>,
+        parent_message_id: Option<&str>,
+        attachments: &[Attachment],
+        generation: &Generation,
+    ) -> Result<(), ChatError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        Self::add_message_on(
+            &tx,
+            conversation_id,
+            &generation.message_id,
+            text,
+            reasoning,
+            &MessageRole::Assistant.to_string(),
+            parent_message_id,
+        )?;
+        for attachment in attachments {
+            Attachments::add_on(&tx, attachment)?;
+        }
+        Generations::set_on(&tx, generation)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Gets a message together with the compaction point recorded on it
+    pub fn get_message(&mut self, message_id: &str) -> Result<Message, ChatError> {
+        Ok(self.messages_manager.get(message_id)?)
+    }
+
+    /// Gets all messages of a conversation, oldest first
     pub fn get_all_message_involved(
         &mut self,
         conversation_id: &str,
     ) -> Result<Vec<Message>, ChatError> {
-        let conv =
-            self.conversation_manager
-                .get(conversation_id)?
-                .ok_or(ChatError::Conversation(ConversationError::Database(
-                    rusqlite::Error::QueryReturnedNoRows,
-                )))?;
+        if !self.conversation_manager.exists(conversation_id)? {
+            return Err(ChatError::Conversation(ConversationError::Database(
+                rusqlite::Error::QueryReturnedNoRows,
+            )));
+        }
 
-        if conv.entry_message_id.is_none() {
-            return Ok(vec![]);
+        let mut messages = self.messages_manager.list_by_conversation(conversation_id)?;
+        let mut compactions: HashMap<_, _> = self
+            .compactions_manager
+            .list_by_conversation(conversation_id)?
+            .into_iter()
+            .map(|c| (c.message_id.clone(), c))
+            .collect();
+        for message in &mut messages {
+            message.compaction = compactions.remove(&message.id);
         }
 
-        let entry_id = conv
-            .entry_message_id
-            .as_deref()
+        Ok(messages)
+    }
+
+    /// Gets the messages on the path from the conversation root down to
+    /// `leaf_message_id`, in order
+    pub fn get_branch_path(&mut self, leaf_message_id: &str) -> Result<Vec<Message>, ChatError> {
+        let mut path = self
+            .messages_manager
+            .get_branch(leaf_message_id, Threads::TABLE_NAME)?;
+        let mut compactions: HashMap<_, _> = self
+            .compactions_manager
+            .list_on_branch(leaf_message_id, Threads::TABLE_NAME)?
+            .into_iter()
+            .map(|c| (c.message_id.clone(), c))
+            .collect();
+        for message in &mut path {
+            message.compaction = compactions.remove(&message.id);
+        }
+        Ok(path)
+    }
+
+    /// Switches the active branch of a conversation to the one ending at
+    /// `leaf_message_id`
+    pub fn set_active_leaf(&mut self, conversation_id: &str, leaf_message_id: &str) -> Result<(), ChatError> {
+        let owner = self.messages_manager.get_conversation_id(leaf_message_id)?;
+        if owner.as_deref() != Some(conversation_id) {
+            return Err(ChatError::Conversation(ConversationError::InvalidOperation(format!(
+                "Message {} does not belong to conversation {}",
+                leaf_message_id, conversation_id
+            ))));
+        }
+        self.conversation_manager
+            .update_active_leaf_id(conversation_id, Some(leaf_message_id))?;
+        Ok(())
+    }
+
+    /// Moves `message_id` and its replies under `new_parent_id` in the same
+    /// conversation. Without a new parent the message becomes the root and
+    /// the previous root is moved under it.
+    pub fn move_subtree(&mut self, message_id: &str, new_parent_id: Option<&str>) -> Result<(), ChatError> {
+        let conversation_id = self
+            .messages_manager
+            .get_conversation_id(message_id)?
+            .ok_or_else(|| ConversationError::InvalidOperation(format!(
+                "Message {} does not belong to a conversation",
+                message_id
+            )))?;
+        let conv = self
+            .conversation_manager
+            .get(&conversation_id)?
             .ok_or(ChatError::Conversation(ConversationError::Database(
-                rusqlite::Error::InvalidQuery,
+                rusqlite::Error::QueryReturnedNoRows,
             )))?;
 
-        // Start with the entry message
-        let mut messages = vec![self.messages_manager.get(entry_id)?];
-
-        // Recursively get all threaded messages
-        let mut current_level = vec![entry_id.to_string()];
-        while !current_level.is_empty() {
-            let mut next_level = Vec::new();
-            for parent_id in &current_level {
-                let children = self.thread_manager.get_children(parent_id)?;
-                for child_id in children {
-                    messages.push(self.messages_manager.get(&child_id)?);
-                    next_level.push(child_id);
-                }
+        if let Some(new_parent_id) = new_parent_id {
+            let parent_conversation_id = self.messages_manager.get_conversation_id(new_parent_id)?;
+            if parent_conversation_id.as_deref() != Some(conversation_id.as_str()) {
+                return Err(ChatError::Conversation(ConversationError::InvalidOperation(
+                    "Messages can only be moved within their conversation".to_string(),
+                )));
             }
-            current_level = next_level;
         }
 
-        Ok(messages)
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        match new_parent_id {
+            Some(new_parent_id) => Threads::update_parent_on(&tx, message_id, Some(new_parent_id))?,
+            None => match conv.entry_message_id.as_deref() {
+                Some(root_id) if root_id != message_id => {
+                    Threads::update_parent_on(&tx, message_id, None)?;
+                    Threads::update_parent_on(&tx, root_id, Some(message_id))?;
+                    Conversations::update_entry_message_id_on(&tx, &conversation_id, Some(message_id))?;
+                }
+                _ => {}
+            },
+        }
+        tx.commit()?;
+        Ok(())
     }
 
-    /// Deletes a conversation and all its messages
-    pub fn delete_conversation(&mut self, conversation_id: &str) -> Result<(), ChatError> {
+    /// Copies the path from the root down to `message_id`, and optionally the
+    /// replies below it, into a new conversation `conversation_id` with fresh
+    /// message IDs. The new conversation records where it was forked from.
+    pub fn fork_conversation(
+        &mut self,
+        conversation_id: &str,
+        message_id: &str,
+        name: &str,
+        include_subtree: bool,
+    ) -> Result<(), ChatError> {
+        let source_id = self
+            .messages_manager
+            .get_conversation_id(message_id)?
+            .ok_or_else(|| ConversationError::InvalidOperation(format!(
+                "Message {} does not belong to a conversation",
+                message_id
+            )))?;
+
+        let path = self.get_branch_path(message_id)?;
+        let mut relations: Vec<(Message, Option<String>)> = vec![];
+        let mut parent_id = None;
+        for message in path {
+            let id = message.id.clone();
+            relations.push((message, parent_id.replace(id)));
+        }
+        if include_subtree {
+            let mut source: HashMap<_, _> = self
+                .get_all_message_involved(&source_id)?
+                .into_iter()
+                .map(|m| (m.id.clone(), m))
+                .collect();
+            let mut children: HashMap<String, Vec<String>> = HashMap::new();
+            for (id, parent_id) in self.thread_manager.get_subtree(message_id)? {
+                if let Some(parent_id) = parent_id {
+                    children.entry(parent_id).or_default().push(id);
+                }
+            }
+            // Breadth first, so every parent is copied before its replies
+            let mut queue = VecDeque::from([message_id.to_string()]);
+            while let Some(parent_id) = queue.pop_front() {
+                for id in children.remove(&parent_id).unwrap_or_default() {
+                    if let Some(message) = source.remove(&id) {
+                        relations.push((message, Some(parent_id.clone())));
+                        queue.push_back(id);
+                    }
+                }
+            }
+        }
+
+        let message_ids: Vec<&str> = relations.iter().map(|(m, _)| m.id.as_str()).collect();
+        let attachments = self.attachments_manager.get_by_messages(&message_ids)?;
+        let generations = self.generations_manager.get_by_messages(&message_ids)?;
+
         let mut conn = self.pool.get()?;
         let tx = conn.transaction()?;
+        Conversations::create_on(&tx, conversation_id, name, None, None)?;
+        let new_ids = Self::copy_messages_on(&tx, conversation_id, &relations, attachments, generations)?;
+        Conversations::update_forked_from_on(&tx, conversation_id, &source_id, message_id)?;
+        Conversations::update_entry_message_id_on(&tx, conversation_id, new_ids.get(&relations[0].0.id).map(|id| id.as_str()))?;
+        Conversations::update_active_leaf_id_on(&tx, conversation_id, new_ids.get(message_id).map(|id| id.as_str()))?;
+        tx.commit()?;
+        Ok(())
+    }
 
-        let messages = self.get_all_message_involved(conversation_id);
-        match messages {
-            Ok(messages) => {
-                for message in messages {
-                    self.messages_manager.delete(&message.id)?;
-					self.thread_manager.delete_with_parent(&message.id)?;
-                }
+    /// Copies messages with their relations, attachments, generations and
+    /// compaction points into a conversation on one connection, parents first.
+    /// Returns the new ID of every copied message.
+    fn copy_messages_on(
+        conn: &Connection,
+        conversation_id: &str,
+        relations: &[(Message, Option<String>)],
+        attachments: Vec<Attachment>,
+        generations: Vec<Generation>,
+    ) -> Result<HashMap<String, String>, ChatError> {
+        let new_ids: HashMap<String, String> = relations
+            .iter()
+            .map(|(message, _)| (message.id.clone(), get_uuid_v4()))
+            .collect();
+
+        let mut thread_relations = Vec::with_capacity(relations.len());
+        for (message, parent_id) in relations {
+            let new_id = &new_ids[&message.id];
+            Messages::add_copy_on(conn, conversation_id, new_id, message)?;
+            let new_parent_id = parent_id.as_ref().and_then(|id| new_ids.get(id)).map(|id| id.as_str());
+            thread_relations.push((new_id.as_str(), new_parent_id));
+            if let Some(compaction) = &message.compaction {
+                Compactions::set_on(conn, &Compaction {
+                    message_id: new_id.clone(),
+                    ..compaction.clone()
+                })?;
             }
-            Err(_) => {}
         }
+        Threads::add_batch_on(conn, &thread_relations)?;
+
+        for attachment in attachments {
+            Attachments::add_on(conn, &Attachment {
+                id: get_uuid_v4(),
+                message_id: new_ids[&attachment.message_id].clone(),
+                ..attachment
+            })?;
+        }
+        for generation in generations {
+            Generations::set_on(conn, &Generation {
+                message_id: new_ids[&generation.message_id].clone(),
+                ..generation
+            })?;
+        }
+        Ok(new_ids)
+    }
+
+    /// Reports messages that break the tree invariants, repairing them when
+    /// `repair` is set
+    pub fn check_integrity(&mut self, repair: bool) -> Result<IntegrityReport, ChatError> {
+        integrity::check(&self.pool, repair)
+    }
 
+    /// Deletes a conversation; its messages are removed with it
+    pub fn delete_conversation(&mut self, conversation_id: &str) -> Result<(), ChatError> {
 		self.conversation_manager.delete(conversation_id)?;
+        Ok(())
+    }
 
-        tx.commit()?;
+    /// Moves a conversation into a folder, or out of any folder if `None`
+    pub fn move_conversation_to_folder(
+        &mut self,
+        conversation_id: &str,
+        folder_id: Option<&str>,
+    ) -> Result<(), ChatError> {
+        if !self.conversation_manager.exists(conversation_id)? {
+            return Err(ConversationError::InvalidOperation(format!(
+                "Conversation {} does not exist",
+                conversation_id
+            ))
+            .into());
+        }
+        if let Some(folder_id) = folder_id {
+            self.folders_manager.ensure_exists(folder_id)?;
+        }
+        self.conversation_manager
+            .update_folder_id(conversation_id, folder_id)?;
         Ok(())
     }
 
-    /// Lists all conversations with their names
-    pub fn list_conversations(&mut self) -> Result<Vec<Conversation>, ChatError> {
-        let convs = self.conversation_manager.list()?;
+    /// Tags a conversation, creating the tag if no tag has this name yet
+    pub fn tag_conversation(&mut self, conversation_id: &str, name: &str) -> Result<Tag, ChatError> {
+        if !self.conversation_manager.exists(conversation_id)? {
+            return Err(ConversationError::InvalidOperation(format!(
+                "Conversation {} does not exist",
+                conversation_id
+            ))
+            .into());
+        }
+        let tag = self.tags_manager.get_or_create(name)?;
+        self.tags_manager.add_to_conversation(conversation_id, &tag.id)?;
+        Ok(tag)
+    }
+
+    /// Tags a message, creating the tag if no tag has this name yet
+    pub fn tag_message(&mut self, message_id: &str, name: &str) -> Result<Tag, ChatError> {
+        self.messages_manager.get(message_id)?;
+        let tag = self.tags_manager.get_or_create(name)?;
+        self.tags_manager.add_to_message(message_id, &tag.id)?;
+        Ok(tag)
+    }
+
+    /// Lists conversations sorted, paged and filtered by `query`
+    pub fn list_conversations(&mut self, query: &ConversationQuery) -> Result<Vec<Conversation>, ChatError> {
+        let convs = self.conversation_manager.list(query)?;
         Ok(convs)
     }
 
@@ -193,7 +499,7 @@ impl Chat {
 
             // Delete all children
             for child_id in all_children {
-                self.messages_manager.delete(&child_id)?;
+                Messages::delete_on(&tx, &child_id)?;
             }
 
             let parent = self.thread_manager.get_parent(message_id)?;
@@ -204,12 +510,11 @@ impl Chat {
                     .ok_or(ChatError::Conversation(ConversationError::Database(
                         rusqlite::Error::QueryReturnedNoRows,
                     )))?;
-                self.conversation_manager
-                    .update_entry_message_id(&conversation.id, None)?
+                Conversations::update_entry_message_id_on(&tx, &conversation.id, None)?
             }
 
             // Delete the original message
-            self.messages_manager.delete(message_id)?;
+            Messages::delete_on(&tx, message_id)?;
 
             tx.commit()?;
             Ok(None)
@@ -220,7 +525,7 @@ impl Chat {
             match &parent {
                 Some(p) => {
                     for child in &children {
-                        self.thread_manager.update_parent(child, Some(p));
+                        Threads::update_parent_on(&tx, child, Some(p))?;
                     }
                 }
                 None => {
@@ -233,8 +538,7 @@ impl Chat {
                                 .ok_or(ChatError::Conversation(ConversationError::Database(
                                     rusqlite::Error::QueryReturnedNoRows,
                                 )))?;
-                            self.conversation_manager
-                                .update_entry_message_id(&conversation.id, None)?
+                            Conversations::update_entry_message_id_on(&tx, &conversation.id, None)?
                         }
                         1 => {
                             let conversation = self
@@ -243,8 +547,9 @@ impl Chat {
                                 .ok_or(ChatError::Conversation(ConversationError::Database(
                                     rusqlite::Error::QueryReturnedNoRows,
                                 )))?;
-                            self.conversation_manager
-                                .update_entry_message_id(&conversation.id, Some(&children[0]))?
+                            // The only reply becomes the new root
+                            Threads::update_parent_on(&tx, &children[0], None)?;
+                            Conversations::update_entry_message_id_on(&tx, &conversation.id, Some(&children[0]))?
                         }
                         _ => {
                             return Err(ChatError::Conversation(
@@ -258,7 +563,7 @@ impl Chat {
             };
 
             // Delete message
-            self.messages_manager.delete(message_id)?;
+            Messages::delete_on(&tx, message_id)?;
             tx.commit()?;
             Ok(parent)
         }
@@ -281,20 +586,141 @@ impl Chat {
             return Ok(vec![]);
         };
 
-        let mut result: Vec<ThreadTreeItem> = vec![];
-        let mut stack = vec![entry_id.clone()];
+        let relations = self.thread_manager.get_subtree(&entry_id)?;
+        let mut children: HashMap<&str, Vec<String>> = HashMap::new();
+        for (id, parent) in &relations {
+            if let Some(parent) = parent {
+                children.entry(parent.as_str()).or_default().push(id.clone());
+            }
+        }
+
+        let result = relations
+            .iter()
+            .map(|(id, parent)| ThreadTreeItem {
+                key: id.clone(),
+                parent: parent.clone(),
+                children: children.remove(id.as_str()).unwrap_or_default(),
+            })
+            .collect();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    const MESSAGE_COUNT: usize = 10_000;
+
+    /// Opens a chat database in a fresh temporary directory
+    fn open_temp_chat(name: &str) -> (Chat, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("wisp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let chat = Chat::open(dir.join("messages.db").to_str().unwrap()).unwrap();
+        (chat, dir)
+    }
+
+    /// Stores a conversation of `MESSAGE_COUNT` messages in which every seventh
+    /// message branches off halfway up the thread. Returns the message IDs.
+    fn build_branched_conversation(chat: &mut Chat, conversation_id: &str) -> Vec<String> {
+        let ids: Vec<String> = (0..MESSAGE_COUNT).map(|i| format!("m{}", i)).collect();
+        let texts: Vec<String> = (0..MESSAGE_COUNT).map(|i| format!("Message {}", i)).collect();
+        let senders = [MessageRole::User.to_string(), MessageRole::Assistant.to_string()];
+
+        chat.conversation_manager
+            .create(conversation_id, "Branched", None, None)
+            .unwrap();
+        let rows: Vec<_> = (0..MESSAGE_COUNT)
+            .map(|i| (ids[i].as_str(), texts[i].as_str(), None, senders[i % 2].as_str(), None, None))
+            .collect();
+        chat.messages_manager.add_batch(conversation_id, &rows).unwrap();
+
+        let relations: Vec<(&str, &str)> = (1..MESSAGE_COUNT)
+            .map(|i| {
+                let parent = if i % 7 == 0 { i / 2 } else { i - 1 };
+                (ids[i].as_str(), ids[parent].as_str())
+            })
+            .collect();
+        chat.thread_manager.add(&ids[0], None).unwrap();
+        chat.thread_manager.add_batch(&relations).unwrap();
+        chat.conversation_manager
+            .update_entry_message_id(conversation_id, Some(&ids[0]))
+            .unwrap();
+        ids
+    }
 
+    /// The tree and messages loaded the way they were before the recursive
+    /// queries, with one lookup per node
+    fn load_per_node(chat: &mut Chat, entry_id: &str) -> (Vec<ThreadTreeItem>, Vec<Message>) {
+        let mut tree = vec![];
+        let mut messages = vec![];
+        let mut stack = vec![entry_id.to_string()];
         while let Some(message_id) = stack.pop() {
-            let children_ids = self.thread_manager.get_children(&message_id)?;
-            let parent_id = self.thread_manager.get_parent(&message_id)?;
-            result.push(ThreadTreeItem {
-                key: message_id.clone(),
-                parent: parent_id,
-                children: children_ids.clone(),
+            let children = chat.thread_manager.get_children(&message_id).unwrap();
+            let parent = chat.thread_manager.get_parent(&message_id).unwrap();
+            messages.push(chat.messages_manager.get(&message_id).unwrap());
+            tree.push(ThreadTreeItem {
+                key: message_id,
+                parent,
+                children: children.clone(),
             });
-            stack.extend(children_ids);
+            stack.extend(children);
         }
+        (tree, messages)
+    }
 
-        Ok(result)
+    fn timed<T>(load: impl FnOnce() -> T) -> (T, Duration) {
+        let started = Instant::now();
+        let result = load();
+        (result, started.elapsed())
+    }
+
+    #[test]
+    fn large_branched_conversation_loads_in_full_and_faster_than_per_node() {
+        let (mut chat, dir) = open_temp_chat("large-conversation");
+        let ids = build_branched_conversation(&mut chat, "c");
+        let expected: HashSet<&str> = ids.iter().map(String::as_str).collect();
+
+        let ((old_tree, old_messages), per_node) = timed(|| load_per_node(&mut chat, &ids[0]));
+        let ((tree, messages), recursive) = timed(|| {
+            let tree = chat.get_thread_tree("c").unwrap();
+            let messages = chat.get_all_message_involved("c").unwrap();
+            (tree, messages)
+        });
+
+        assert_eq!(tree.len(), MESSAGE_COUNT);
+        assert_eq!(tree.iter().map(|item| item.key.as_str()).collect::<HashSet<_>>(), expected);
+        assert_eq!(tree.iter().filter(|item| item.parent.is_none()).count(), 1);
+        let branch_point = tree.iter().find(|item| item.key == "m7").unwrap();
+        assert_eq!(branch_point.parent.as_deref(), Some("m3"));
+
+        // Same relations as the per-node walk, whatever the order
+        let relations = |tree: &[ThreadTreeItem]| {
+            tree.iter()
+                .map(|item| {
+                    let mut children = item.children.clone();
+                    children.sort();
+                    (item.key.clone(), item.parent.clone(), children)
+                })
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(relations(&tree), relations(&old_tree));
+
+        assert_eq!(messages.len(), MESSAGE_COUNT);
+        assert_eq!(messages.iter().map(|m| m.id.as_str()).collect::<HashSet<_>>(), expected);
+        assert_eq!(old_messages.len(), MESSAGE_COUNT);
+
+        assert!(
+            recursive < per_node,
+            "recursive queries took {:?}, per-node lookups {:?}",
+            recursive,
+            per_node
+        );
+
+        drop(chat);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
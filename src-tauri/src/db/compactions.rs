@@ -0,0 +1,138 @@
+use rusqlite::{params, Connection};
+use super::DbPool;
+use super::messages::Messages;
+use super::types::{Compaction, MessageError};
+
+/// Summaries that stand in for the history above a message. The summarised
+/// messages themselves are kept.
+pub struct Compactions {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Compactions {
+    pub const TABLE_NAME: &'static str = "compactions";
+
+    pub fn new(pool: DbPool, message_table_name: &str) -> Result<Self, MessageError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+					message_id TEXT PRIMARY KEY,
+					summary TEXT NOT NULL,
+					provider TEXT NOT NULL,
+					model TEXT NOT NULL,
+					FOREIGN KEY (message_id) REFERENCES {} (id) ON DELETE CASCADE
+				)",
+                Self::TABLE_NAME,
+                message_table_name
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    /// Record a compaction point, replacing any previous one at the same message.
+    pub fn set(&mut self, compaction: &Compaction) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        Self::set_on(&conn, compaction)
+    }
+
+    /// `set` on a given connection, so it can be part of a transaction.
+    pub fn set_on(conn: &Connection, compaction: &Compaction) -> Result<(), MessageError> {
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (message_id, summary, provider, model) VALUES (?1, ?2, ?3, ?4)",
+                Self::TABLE_NAME
+            ),
+            params![compaction.message_id, compaction.summary, compaction.provider, compaction.model],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&mut self, message_id: &str) -> Result<Option<Compaction>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT message_id, summary, provider, model FROM {} WHERE message_id = ?1",
+            Self::TABLE_NAME
+        ))?;
+
+        let result = stmt.query_row(params![message_id], |row| {
+            Ok(Compaction {
+                message_id: row.get(0)?,
+                summary: row.get(1)?,
+                provider: row.get(2)?,
+                model: row.get(3)?,
+            })
+        });
+
+        match result {
+            Ok(compaction) => Ok(Some(compaction)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Compaction points on the messages of a conversation
+    pub fn list_by_conversation(&mut self, conversation_id: &str) -> Result<Vec<Compaction>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT c.message_id, c.summary, c.provider, c.model FROM {} c
+				JOIN {} m ON m.id = c.message_id
+				WHERE m.conversation_id = ?1",
+            Self::TABLE_NAME,
+            Messages::TABLE_NAME
+        ))?;
+
+        let compactions = stmt
+            .query_map(params![conversation_id], |row| {
+                Ok(Compaction {
+                    message_id: row.get(0)?,
+                    summary: row.get(1)?,
+                    provider: row.get(2)?,
+                    model: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(compactions)
+    }
+
+    /// Compaction points on the path from the root of the thread down to
+    /// `leaf_id`, walking the parent links in `thread_table_name`
+    pub fn list_on_branch(&mut self, leaf_id: &str, thread_table_name: &str) -> Result<Vec<Compaction>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "WITH RECURSIVE branch (id) AS (
+				SELECT ?1
+				UNION ALL
+				SELECT t.parent_id FROM {1} t JOIN branch ON t.id = branch.id
+				WHERE t.parent_id IS NOT NULL
+			)
+			SELECT c.message_id, c.summary, c.provider, c.model FROM branch JOIN {0} c ON c.message_id = branch.id",
+            Self::TABLE_NAME,
+            thread_table_name
+        ))?;
+
+        let compactions = stmt
+            .query_map(params![leaf_id], |row| {
+                Ok(Compaction {
+                    message_id: row.get(0)?,
+                    summary: row.get(1)?,
+                    provider: row.get(2)?,
+                    model: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(compactions)
+    }
+
+    pub fn delete(&mut self, message_id: &str) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE message_id = ?1", Self::TABLE_NAME),
+            params![message_id],
+        )?;
+        Ok(())
+    }
+}
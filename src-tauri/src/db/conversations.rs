@@ -1,6 +1,8 @@
-use rusqlite::params;
+use rusqlite::{params, Connection};
 use super::DbPool;
-use super::types::{Conversation, ConversationError};
+use super::tags::Tags;
+use super::types::{Conversation, ConversationError, ConversationQuery, ConversationSort, FolderFilter};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct Conversations {
     pool: DbPool,
@@ -9,6 +11,8 @@ pub struct Conversations {
 #[allow(unused)]
 impl Conversations {
     pub const TABLE_NAME: &'static str = "conversations";
+    /// Columns read into a `Conversation` by `from_row`
+    const COLUMNS: &'static str = "id, name, description, entry_message_id, active_leaf_id, forked_from_conversation_id, forked_from_message_id, created_at, updated_at, pinned, archived, folder_id";
 
     pub fn new(pool: DbPool, message_table_name: &str) -> Result<Self, ConversationError> {
         let conn = pool.get().map_err(ConversationError::Pool)?;
@@ -33,18 +37,33 @@ impl Conversations {
 
 	pub fn create(&mut self, id: &str, name: &str, description: Option<&str>, entry_message_id: Option<&str>) -> Result<(), ConversationError> {
 		let conn = self.pool.get()?;
+		Self::create_on(&conn, id, name, description, entry_message_id)
+	}
+
+	/// `create` on a given connection, so it can be part of a transaction.
+	pub fn create_on(conn: &Connection, id: &str, name: &str, description: Option<&str>, entry_message_id: Option<&str>) -> Result<(), ConversationError> {
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_secs() as i64;
+
 		conn.execute(
 			&format!(
-				"INSERT INTO {} (id, name, description, entry_message_id) VALUES (?1, ?2, ?3, ?4)",
+				"INSERT INTO {} (id, name, description, entry_message_id, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
 				Self::TABLE_NAME
 			),
-			params![id, name, description, entry_message_id],
+			params![id, name, description, entry_message_id, now],
 		)?;
 		Ok(())
 	}
 
 	pub fn exists(&mut self, id: &str) -> Result<bool, ConversationError> {
 		let conn = self.pool.get()?;
+		Self::exists_on(&conn, id)
+	}
+
+	/// `exists` on a given connection, so it can be part of a transaction.
+	pub fn exists_on(conn: &Connection, id: &str) -> Result<bool, ConversationError> {
 		let mut stmt = conn.prepare(&format!(
 			"SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)",
 			Self::TABLE_NAME
@@ -56,18 +75,12 @@ impl Conversations {
 	pub fn get(&mut self, id: &str) -> Result<Option<Conversation>, ConversationError> {
 		let conn = self.pool.get()?;
 		let mut stmt = conn.prepare(&format!(
-			"SELECT * FROM {} WHERE id = ?1",
+			"SELECT {} FROM {} WHERE id = ?1",
+			Self::COLUMNS,
 			Self::TABLE_NAME
 		))?;
 
-		let result = stmt.query_row(params![id], |row| {
-			Ok(Conversation {
-				id: row.get(0)?,
-				name: row.get(1)?,
-				description: row.get(2)?,
-				entry_message_id: row.get(3)?,
-			})
-		});
+		let result = stmt.query_row(params![id], Self::from_row);
 
 		match result {
 			Ok(conv) => Ok(Some(conv)),
@@ -79,18 +92,12 @@ impl Conversations {
 	pub fn get_by_entry_id(&mut self, id: &str) -> Result<Option<Conversation>, ConversationError> {
 		let conn = self.pool.get()?;
 		let mut stmt = conn.prepare(&format!(
-			"SELECT * FROM {} WHERE entry_message_id = ?1",
+			"SELECT {} FROM {} WHERE entry_message_id = ?1",
+			Self::COLUMNS,
 			Self::TABLE_NAME
 		))?;
 
-		let result = stmt.query_row(params![id], |row| {
-			Ok(Conversation {
-				id: row.get(0)?,
-				name: row.get(1)?,
-				description: row.get(2)?,
-				entry_message_id: row.get(3)?,
-			})
-		});
+		let result = stmt.query_row(params![id], Self::from_row);
 
 		match result {
 			Ok(conv) => Ok(Some(conv)),
@@ -125,6 +132,11 @@ impl Conversations {
 
 	pub fn update_entry_message_id(&mut self, id: &str, entry_message_id: Option<&str>) -> Result<(), ConversationError> {
 		let conn = self.pool.get()?;
+		Self::update_entry_message_id_on(&conn, id, entry_message_id)
+	}
+
+	/// `update_entry_message_id` on a given connection, so it can be part of a transaction.
+	pub fn update_entry_message_id_on(conn: &Connection, id: &str, entry_message_id: Option<&str>) -> Result<(), ConversationError> {
 		conn.execute(
 			&format!(
 				"UPDATE {} SET entry_message_id = ?2 WHERE id = ?1",
@@ -135,6 +147,78 @@ impl Conversations {
 		Ok(())
 	}
 
+	/// Record the conversation and message a conversation was forked from
+	pub fn update_forked_from(&mut self, id: &str, conversation_id: &str, message_id: &str) -> Result<(), ConversationError> {
+		let conn = self.pool.get()?;
+		Self::update_forked_from_on(&conn, id, conversation_id, message_id)
+	}
+
+	/// `update_forked_from` on a given connection, so it can be part of a transaction.
+	pub fn update_forked_from_on(conn: &Connection, id: &str, conversation_id: &str, message_id: &str) -> Result<(), ConversationError> {
+		conn.execute(
+			&format!(
+				"UPDATE {} SET forked_from_conversation_id = ?2, forked_from_message_id = ?3 WHERE id = ?1",
+				Self::TABLE_NAME
+			),
+			params![id, conversation_id, message_id],
+		)?;
+		Ok(())
+	}
+
+	pub fn update_active_leaf_id(&mut self, id: &str, active_leaf_id: Option<&str>) -> Result<(), ConversationError> {
+		let conn = self.pool.get()?;
+		Self::update_active_leaf_id_on(&conn, id, active_leaf_id)
+	}
+
+	/// `update_active_leaf_id` on a given connection, so it can be part of a transaction.
+	pub fn update_active_leaf_id_on(conn: &Connection, id: &str, active_leaf_id: Option<&str>) -> Result<(), ConversationError> {
+		conn.execute(
+			&format!(
+				"UPDATE {} SET active_leaf_id = ?2 WHERE id = ?1",
+				Self::TABLE_NAME
+			),
+			params![id, active_leaf_id],
+		)?;
+		Ok(())
+	}
+
+	pub fn update_pinned(&mut self, id: &str, pinned: bool) -> Result<(), ConversationError> {
+		let conn = self.pool.get()?;
+		conn.execute(
+			&format!(
+				"UPDATE {} SET pinned = ?2 WHERE id = ?1",
+				Self::TABLE_NAME
+			),
+			params![id, pinned],
+		)?;
+		Ok(())
+	}
+
+	pub fn update_archived(&mut self, id: &str, archived: bool) -> Result<(), ConversationError> {
+		let conn = self.pool.get()?;
+		conn.execute(
+			&format!(
+				"UPDATE {} SET archived = ?2 WHERE id = ?1",
+				Self::TABLE_NAME
+			),
+			params![id, archived],
+		)?;
+		Ok(())
+	}
+
+	/// Move a conversation into a folder, or out of any folder if `None`
+	pub fn update_folder_id(&mut self, id: &str, folder_id: Option<&str>) -> Result<(), ConversationError> {
+		let conn = self.pool.get()?;
+		conn.execute(
+			&format!(
+				"UPDATE {} SET folder_id = ?2 WHERE id = ?1",
+				Self::TABLE_NAME
+			),
+			params![id, folder_id],
+		)?;
+		Ok(())
+	}
+
 	pub fn delete(&mut self, id: &str) -> Result<(), ConversationError> {
 		let conn = self.pool.get()?;
 		conn.execute(
@@ -147,25 +231,67 @@ impl Conversations {
 		Ok(())
 	}
 
-	pub fn list(&mut self) -> Result<Vec<Conversation>, ConversationError> {
+	/// List conversations sorted, paged and filtered by `query`. Pinned
+	/// conversations come first.
+	pub fn list(&mut self, query: &ConversationQuery) -> Result<Vec<Conversation>, ConversationError> {
+		let column = match query.sort {
+			ConversationSort::Name => "name COLLATE NOCASE",
+			ConversationSort::CreatedAt => "created_at",
+			ConversationSort::UpdatedAt => "updated_at",
+		};
+		let direction = if query.ascending { "ASC" } else { "DESC" };
+		let pattern = query
+			.filter
+			.as_deref()
+			.filter(|f| !f.is_empty())
+			.map(|f| format!("%{}%", f.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")));
+
+		// Without a folder id, `folder_id IS ?6` matches conversations outside any folder
+		let (by_folder, folder_id) = match &query.folder {
+			FolderFilter::Any => (false, None),
+			FolderFilter::Root => (true, None),
+			FolderFilter::Folder(id) => (true, Some(id.as_str())),
+		};
+
+		let tags = serde_json::to_string(&query.tags).unwrap_or_else(|_| "[]".to_string());
+
 		let conn = self.pool.get()?;
 		let mut stmt = conn.prepare(&format!(
-			"SELECT * FROM {} ORDER BY name ASC",
-			Self::TABLE_NAME
+			"SELECT {} FROM {} WHERE (?1 IS NULL OR name LIKE ?1 ESCAPE '\\' OR description LIKE ?1 ESCAPE '\\')
+			AND archived = ?4 AND (NOT ?5 OR folder_id IS ?6)
+			AND (json_array_length(?7) = 0 OR id IN (
+				SELECT conversation_id FROM {} WHERE tag_id IN (SELECT value FROM json_each(?7))
+				GROUP BY conversation_id HAVING COUNT(*) = (SELECT COUNT(DISTINCT value) FROM json_each(?7))
+			))
+			ORDER BY pinned DESC, {} {}, id LIMIT ?2 OFFSET ?3",
+			Self::COLUMNS,
+			Self::TABLE_NAME,
+			Tags::CONVERSATION_TABLE_NAME,
+			column,
+			direction
 		))?;
 
 		let conversations = stmt
-			.query_map(params![], |row| {
-				Ok(Conversation {
-					id: row.get(0)?,
-					name: row.get(1)?,
-					description: row.get(2)?,
-					entry_message_id: row.get(3)?,
-				})
-			})?
+			.query_map(params![pattern, query.limit.unwrap_or(-1), query.offset.unwrap_or(0), query.archived, by_folder, folder_id, tags], Self::from_row)?
 			.collect::<Result<Vec<_>, rusqlite::Error>>()?;
 
 		Ok(conversations)
 	}
 
+	fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Conversation> {
+		Ok(Conversation {
+			id: row.get("id")?,
+			name: row.get("name")?,
+			description: row.get("description")?,
+			entry_message_id: row.get("entry_message_id")?,
+			active_leaf_id: row.get("active_leaf_id")?,
+			forked_from_conversation_id: row.get("forked_from_conversation_id")?,
+			forked_from_message_id: row.get("forked_from_message_id")?,
+			created_at: row.get("created_at")?,
+			updated_at: row.get("updated_at")?,
+			pinned: row.get("pinned")?,
+			archived: row.get("archived")?,
+			folder_id: row.get("folder_id")?,
+		})
+	}
 }
@@ -0,0 +1,75 @@
+use rusqlite::params;
+use super::DbPool;
+use super::types::ConversationError;
+use crate::configs::model::FallbackTarget;
+
+/// Per-conversation fallback chains, overriding the ones configured on models.
+pub struct Fallbacks {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Fallbacks {
+    pub const TABLE_NAME: &'static str = "conversation_fallbacks";
+
+    pub fn new(pool: DbPool, conversation_table_name: &str) -> Result<Self, ConversationError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+					conversation_id TEXT NOT NULL,
+					position INTEGER NOT NULL,
+					provider TEXT NOT NULL,
+					model TEXT NOT NULL,
+					PRIMARY KEY (conversation_id, position),
+					FOREIGN KEY (conversation_id) REFERENCES {} (id) ON DELETE CASCADE
+				)",
+                Self::TABLE_NAME,
+                conversation_table_name
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    /// Replace the fallback chain of a conversation. An empty chain falls back
+    /// to the chain of the model.
+    pub fn set(&mut self, conversation_id: &str, fallbacks: &[FallbackTarget]) -> Result<(), ConversationError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!("DELETE FROM {} WHERE conversation_id = ?1", Self::TABLE_NAME),
+            params![conversation_id],
+        )?;
+        for (position, fallback) in fallbacks.iter().enumerate() {
+            tx.execute(
+                &format!(
+                    "INSERT INTO {} (conversation_id, position, provider, model) VALUES (?1, ?2, ?3, ?4)",
+                    Self::TABLE_NAME
+                ),
+                params![conversation_id, position as i64, fallback.provider, fallback.model],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get(&mut self, conversation_id: &str) -> Result<Vec<FallbackTarget>, ConversationError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT provider, model FROM {} WHERE conversation_id = ?1 ORDER BY position",
+            Self::TABLE_NAME
+        ))?;
+
+        let fallbacks = stmt
+            .query_map(params![conversation_id], |row| {
+                Ok(FallbackTarget {
+                    provider: row.get(0)?,
+                    model: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(fallbacks)
+    }
+}
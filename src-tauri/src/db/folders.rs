@@ -0,0 +1,196 @@
+use rusqlite::{params, Connection};
+use super::DbPool;
+use super::conversations::Conversations;
+use super::types::{Folder, FolderError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Folders that conversations are organised in. Folders nest, and deleting
+/// one moves its subfolders and conversations up to its parent.
+pub struct Folders {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Folders {
+    pub const TABLE_NAME: &'static str = "folders";
+
+    pub fn new(pool: DbPool) -> Result<Self, FolderError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {0} (
+					id TEXT PRIMARY KEY,
+					name TEXT NOT NULL,
+					parent_id TEXT,
+					created_at INTEGER NOT NULL,
+					FOREIGN KEY (parent_id) REFERENCES {0} (id)
+				)",
+                Self::TABLE_NAME
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn create(&mut self, id: &str, name: &str, parent_id: Option<&str>) -> Result<(), FolderError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let conn = self.pool.get()?;
+        if let Some(parent_id) = parent_id {
+            Self::ensure_exists_on(&conn, parent_id)?;
+        }
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (id, name, parent_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+                Self::TABLE_NAME
+            ),
+            params![id, name, parent_id, now],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&mut self, id: &str) -> Result<Option<Folder>, FolderError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, parent_id, created_at FROM {} WHERE id = ?1",
+            Self::TABLE_NAME
+        ))?;
+
+        let result = stmt.query_row(params![id], |row| {
+            Ok(Folder {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                parent_id: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        });
+
+        match result {
+            Ok(folder) => Ok(Some(folder)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every folder, sorted by name. The hierarchy is rebuilt from `parent_id`.
+    pub fn list(&mut self) -> Result<Vec<Folder>, FolderError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name, parent_id, created_at FROM {} ORDER BY name COLLATE NOCASE, id",
+            Self::TABLE_NAME
+        ))?;
+
+        let folders = stmt
+            .query_map([], |row| {
+                Ok(Folder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_id: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(folders)
+    }
+
+    pub fn rename(&mut self, id: &str, name: &str) -> Result<(), FolderError> {
+        let conn = self.pool.get()?;
+        let updated = conn.execute(
+            &format!("UPDATE {} SET name = ?2 WHERE id = ?1", Self::TABLE_NAME),
+            params![id, name],
+        )?;
+        if updated == 0 {
+            return Err(FolderError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Move a folder under `parent_id`, or to the top level if `None`.
+    /// Fails if the new parent is the folder itself or one of its subfolders.
+    pub fn update_parent(&mut self, id: &str, parent_id: Option<&str>) -> Result<(), FolderError> {
+        let conn = self.pool.get()?;
+        Self::ensure_exists_on(&conn, id)?;
+        if let Some(parent_id) = parent_id {
+            Self::ensure_exists_on(&conn, parent_id)?;
+            if Self::is_within_on(&conn, parent_id, id)? {
+                return Err(FolderError::Cycle(id.to_string()));
+            }
+        }
+        conn.execute(
+            &format!("UPDATE {} SET parent_id = ?2 WHERE id = ?1", Self::TABLE_NAME),
+            params![id, parent_id],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a folder, moving its subfolders and conversations to its parent
+    pub fn delete(&mut self, id: &str) -> Result<(), FolderError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let parent_id: Option<String> = tx
+            .query_row(
+                &format!("SELECT parent_id FROM {} WHERE id = ?1", Self::TABLE_NAME),
+                params![id],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => FolderError::NotFound(id.to_string()),
+                e => e.into(),
+            })?;
+
+        tx.execute(
+            &format!("UPDATE {} SET parent_id = ?2 WHERE parent_id = ?1", Self::TABLE_NAME),
+            params![id, parent_id],
+        )?;
+        tx.execute(
+            &format!("UPDATE {} SET folder_id = ?2 WHERE folder_id = ?1", Conversations::TABLE_NAME),
+            params![id, parent_id],
+        )?;
+        tx.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Self::TABLE_NAME),
+            params![id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn ensure_exists(&mut self, id: &str) -> Result<(), FolderError> {
+        let conn = self.pool.get()?;
+        Self::ensure_exists_on(&conn, id)
+    }
+
+    fn ensure_exists_on(conn: &Connection, id: &str) -> Result<(), FolderError> {
+        let exists: bool = conn.query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)", Self::TABLE_NAME),
+            params![id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(FolderError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether `id` is `ancestor_id` or one of its subfolders
+    fn is_within_on(conn: &Connection, id: &str, ancestor_id: &str) -> Result<bool, FolderError> {
+        let within = conn.query_row(
+            &format!(
+                "WITH RECURSIVE ancestors (id) AS (
+					SELECT ?1
+					UNION
+					SELECT f.parent_id FROM {} f JOIN ancestors ON f.id = ancestors.id
+					WHERE f.parent_id IS NOT NULL
+				)
+				SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?2)",
+                Self::TABLE_NAME
+            ),
+            params![id, ancestor_id],
+            |row| row.get(0),
+        )?;
+        Ok(within)
+    }
+}
@@ -0,0 +1,99 @@
+use rusqlite::{params, Connection};
+use super::DbPool;
+use super::types::{Generation, MessageError};
+
+/// Records which provider, model and parameters produced a bot message.
+pub struct Generations {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Generations {
+    pub const TABLE_NAME: &'static str = "generations";
+
+    pub fn new(pool: DbPool, message_table_name: &str) -> Result<Self, MessageError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+					message_id TEXT PRIMARY KEY,
+					provider TEXT NOT NULL,
+					model TEXT NOT NULL,
+					parameters TEXT,
+					FOREIGN KEY (message_id) REFERENCES {} (id) ON DELETE CASCADE
+				)",
+                Self::TABLE_NAME,
+                message_table_name
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    /// Record the generation of a message, replacing any previous record.
+    pub fn set(&mut self, generation: &Generation) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        Self::set_on(&conn, generation)
+    }
+
+    /// `set` on a given connection, so it can be part of a transaction.
+    pub fn set_on(conn: &Connection, generation: &Generation) -> Result<(), MessageError> {
+        let parameters = generation.parameters.as_ref().map(|p| p.to_string());
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (message_id, provider, model, parameters) VALUES (?1, ?2, ?3, ?4)",
+                Self::TABLE_NAME
+            ),
+            params![generation.message_id, generation.provider, generation.model, parameters],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&mut self, message_id: &str) -> Result<Option<Generation>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT message_id, provider, model, parameters FROM {} WHERE message_id = ?1",
+            Self::TABLE_NAME
+        ))?;
+
+        let result = stmt.query_row(params![message_id], |row| {
+            let parameters: Option<String> = row.get(3)?;
+            Ok(Generation {
+                message_id: row.get(0)?,
+                provider: row.get(1)?,
+                model: row.get(2)?,
+                parameters: parameters.and_then(|p| serde_json::from_str(&p).ok()),
+            })
+        });
+
+        match result {
+            Ok(generation) => Ok(Some(generation)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The generation records of several messages at once
+    pub fn get_by_messages(&mut self, message_ids: &[&str]) -> Result<Vec<Generation>, MessageError> {
+        let ids = serde_json::to_string(message_ids).unwrap_or_else(|_| "[]".to_string());
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT message_id, provider, model, parameters FROM {} WHERE message_id IN (SELECT value FROM json_each(?1))",
+            Self::TABLE_NAME
+        ))?;
+
+        let generations = stmt
+            .query_map(params![ids], |row| {
+                let parameters: Option<String> = row.get(3)?;
+                Ok(Generation {
+                    message_id: row.get(0)?,
+                    provider: row.get(1)?,
+                    model: row.get(2)?,
+                    parameters: parameters.and_then(|p| serde_json::from_str(&p).ok()),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(generations)
+    }
+}
@@ -0,0 +1,150 @@
+use rusqlite::{params, Connection};
+use super::DbPool;
+use super::conversations::Conversations;
+use super::messages::Messages;
+use super::threads::Threads;
+use super::types::{ChatError, IntegrityReport};
+
+/// Finds messages that break the tree invariants and, when `repair` is set,
+/// fixes them in one transaction. Messages with several parents keep the
+/// relation that was recorded first. Messages without a conversation take the
+/// one of their closest ancestor that has one; those left without are
+/// deleted. Messages whose parent is missing are re-attached under the entry
+/// message of their conversation, or become the entry if it has none.
+pub fn check(pool: &DbPool, repair: bool) -> Result<IntegrityReport, ChatError> {
+    let mut conn = pool.get()?;
+    let mut report = find_problems(&conn)?;
+    let has_problems = !report.multiple_parents.is_empty()
+        || !report.dangling_parents.is_empty()
+        || !report.without_conversation.is_empty();
+    if !repair || !has_problems {
+        return Ok(report);
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        &format!(
+            "DELETE FROM {0} WHERE rowid NOT IN (SELECT MIN(rowid) FROM {0} GROUP BY id)",
+            Threads::TABLE_NAME
+        ),
+        [],
+    )?;
+
+    tx.execute(
+        &format!(
+            "WITH RECURSIVE up (id, ancestor_id, depth) AS (
+				SELECT t.id, t.parent_id, 1 FROM {0} t WHERE t.id IN ({2}) AND t.parent_id IS NOT NULL
+				UNION ALL
+				SELECT up.id, t.parent_id, up.depth + 1 FROM {0} t JOIN up ON t.id = up.ancestor_id
+				WHERE t.parent_id IS NOT NULL
+			)
+			UPDATE {1} SET conversation_id = COALESCE(
+				(SELECT c.id FROM {3} c WHERE c.entry_message_id = {1}.id),
+				(SELECT a.conversation_id FROM up
+					JOIN {1} a ON a.id = up.ancestor_id
+					JOIN {3} c ON c.id = a.conversation_id
+					WHERE up.id = {1}.id ORDER BY up.depth LIMIT 1)
+			)
+			WHERE id IN ({2})",
+            Threads::TABLE_NAME,
+            Messages::TABLE_NAME,
+            without_conversation_query(),
+            Conversations::TABLE_NAME
+        ),
+        [],
+    )?;
+
+    // Replies that belong to a conversation lose their relation with these
+    // and are re-attached below
+    report.deleted = query_ids(&tx, &without_conversation_query())?;
+    tx.execute(
+        &format!(
+            "DELETE FROM {} WHERE id IN ({})",
+            Messages::TABLE_NAME,
+            without_conversation_query()
+        ),
+        [],
+    )?;
+
+    for id in query_ids(&tx, &dangling_parents_query())? {
+        let (conversation_id, entry_id): (String, Option<String>) = tx.query_row(
+            &format!(
+                "SELECT c.id, c.entry_message_id FROM {} m JOIN {} c ON c.id = m.conversation_id WHERE m.id = ?1",
+                Messages::TABLE_NAME,
+                Conversations::TABLE_NAME
+            ),
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Threads::TABLE_NAME),
+            params![id],
+        )?;
+        match entry_id {
+            Some(entry_id) if entry_id != id => {
+                tx.execute(
+                    &format!("INSERT INTO {} (id, parent_id) VALUES (?1, ?2)", Threads::TABLE_NAME),
+                    params![id, entry_id],
+                )?;
+            }
+            Some(_) => {}
+            None => Conversations::update_entry_message_id_on(&tx, &conversation_id, Some(&id))?,
+        }
+        report.reattached.push(id);
+    }
+    tx.commit()?;
+
+    report.repaired = true;
+    Ok(report)
+}
+
+fn find_problems(conn: &Connection) -> Result<IntegrityReport, ChatError> {
+    let multiple_parents = query_ids(
+        conn,
+        &format!(
+            "SELECT id FROM {} GROUP BY id HAVING COUNT(*) > 1",
+            Threads::TABLE_NAME
+        ),
+    )?;
+
+    Ok(IntegrityReport {
+        multiple_parents,
+        dangling_parents: query_ids(conn, &dangling_parents_query())?,
+        without_conversation: query_ids(conn, &without_conversation_query())?,
+        ..Default::default()
+    })
+}
+
+/// Messages whose parent is gone, or that have no relation at all while not
+/// being the entry of a conversation
+fn dangling_parents_query() -> String {
+    format!(
+        "SELECT t.id FROM {0} t
+			WHERE t.parent_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM {1} p WHERE p.id = t.parent_id)
+		UNION
+		SELECT m.id FROM {1} m
+			WHERE NOT EXISTS (SELECT 1 FROM {0} t WHERE t.id = m.id)
+			AND m.id NOT IN (SELECT entry_message_id FROM {2} WHERE entry_message_id IS NOT NULL)",
+        Threads::TABLE_NAME,
+        Messages::TABLE_NAME,
+        Conversations::TABLE_NAME
+    )
+}
+
+fn without_conversation_query() -> String {
+    format!(
+        "SELECT m.id FROM {0} m
+			WHERE m.conversation_id IS NULL
+			OR NOT EXISTS (SELECT 1 FROM {1} c WHERE c.id = m.conversation_id)",
+        Messages::TABLE_NAME,
+        Conversations::TABLE_NAME
+    )
+}
+
+fn query_ids(conn: &Connection, sql: &str) -> Result<Vec<String>, ChatError> {
+    let mut stmt = conn.prepare(sql)?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
@@ -1,8 +1,9 @@
-use rusqlite::params;
+use rusqlite::{params, Connection};
 use super::DbPool;
 
 use std::time::{SystemTime, UNIX_EPOCH};
-use super::types::{MessageError, MessageRole, Message};
+use super::compactions::Compactions;
+use super::types::{Compaction, MessageError, MessageRole, Message};
 
 pub struct Messages {
     pool: DbPool,
@@ -33,24 +34,49 @@ impl Messages {
         Ok(Self { pool })
     }
 
-    pub fn add(&mut self, id: &str, text: &str, reasoning: Option<&str>, sender: &str, tokens: Option<i32>, embedding: Option<Vec<u8>>) -> Result<(), MessageError> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn add(&mut self, conversation_id: &str, id: &str, text: &str, reasoning: Option<&str>, sender: &str, tokens: Option<i32>, embedding: Option<Vec<u8>>) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        Self::add_on(&conn, conversation_id, id, text, reasoning, sender, tokens, embedding)
+    }
+
+    /// `add` on a given connection, so it can be part of a transaction.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_on(conn: &Connection, conversation_id: &str, id: &str, text: &str, reasoning: Option<&str>, sender: &str, tokens: Option<i32>, embedding: Option<Vec<u8>>) -> Result<(), MessageError> {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs() as i64;
 
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (id, conversation_id, text, reasoning, sender, timestamp, tokens, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                Self::TABLE_NAME
+            ),
+            params![id, conversation_id, text, reasoning, sender, timestamp, tokens, embedding],
+        )?;
+        Ok(())
+    }
+
+    /// Insert a copy of `message` under a new ID, keeping its timestamp
+    pub fn add_copy(&mut self, conversation_id: &str, id: &str, message: &Message) -> Result<(), MessageError> {
         let conn = self.pool.get()?;
+        Self::add_copy_on(&conn, conversation_id, id, message)
+    }
+
+    /// `add_copy` on a given connection, so it can be part of a transaction.
+    pub fn add_copy_on(conn: &Connection, conversation_id: &str, id: &str, message: &Message) -> Result<(), MessageError> {
         conn.execute(
             &format!(
-                "INSERT INTO {} (id, text, reasoning, sender, timestamp, tokens, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                "INSERT INTO {} (id, conversation_id, text, reasoning, sender, timestamp, tokens, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
                 Self::TABLE_NAME
             ),
-            params![id, text, reasoning, sender, timestamp, tokens, embedding],
+            params![id, conversation_id, message.text, message.reasoning, message.sender.to_string(), message.timestamp, message.tokens, message.embedding],
         )?;
         Ok(())
     }
 
-    pub fn add_batch(&mut self, messages: &[(&str, &str, Option<&str>, &str, Option<i32>, Option<Vec<u8>>)]) -> Result<(), MessageError> {
+    pub fn add_batch(&mut self, conversation_id: &str, messages: &[(&str, &str, Option<&str>, &str, Option<i32>, Option<Vec<u8>>)]) -> Result<(), MessageError> {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -60,29 +86,41 @@ impl Messages {
         let tx = conn.transaction()?;
         {
             let mut stmt = tx.prepare(&format!(
-                "INSERT INTO {} (id, text, reasoning, sender, timestamp, tokens, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                "INSERT INTO {} (id, conversation_id, text, reasoning, sender, timestamp, tokens, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
                 Self::TABLE_NAME
             ))?;
 
             for (id, text, reasoning, sender, tokens, embedding) in messages {
-                stmt.execute(params![id, text, reasoning, sender, timestamp, tokens, embedding])?;
+                stmt.execute(params![id, conversation_id, text, reasoning, sender, timestamp, tokens, embedding])?;
             }
         }
         tx.commit()?;
         Ok(())
     }
 
+    /// Get a message together with the compaction point recorded on it
     pub fn get(&mut self, id: &str) -> Result<Message, MessageError> {
         let conn = self.pool.get()?;
         let mut stmt = conn.prepare(&format!(
-            "SELECT id, text, reasoning, sender, timestamp, tokens, embedding FROM {} WHERE id = ?1",
-            Self::TABLE_NAME
+            "SELECT m.id, m.text, m.reasoning, m.sender, m.timestamp, m.tokens, m.embedding, c.summary, c.provider, c.model
+				FROM {} m LEFT JOIN {} c ON c.message_id = m.id WHERE m.id = ?1",
+            Self::TABLE_NAME,
+            Compactions::TABLE_NAME
         ))?;
 
         let row = stmt.query_row(params![id], |row| {
 			let sender_str: String = row.get(3)?;
             let sender = MessageRole::try_from(sender_str)
                 .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+            let compaction = match row.get::<_, Option<String>>(7)? {
+                Some(summary) => Some(Compaction {
+                    message_id: row.get(0)?,
+                    summary,
+                    provider: row.get(8)?,
+                    model: row.get(9)?,
+                }),
+                None => None,
+            };
             Ok(Message {
                 id: row.get(0)?,
                 text: row.get(1)?,
@@ -91,6 +129,7 @@ impl Messages {
                 timestamp: row.get(4)?,
                 tokens: row.get(5)?,
                 embedding: row.get(6)?,
+                compaction,
             })
         })?;
         Ok(row)
@@ -116,11 +155,96 @@ impl Messages {
                     timestamp: row.get(4)?,
                     tokens: row.get(5)?,
                     embedding: row.get(6)?,
+                    compaction: None,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()
+            .map_err(MessageError::from)?;
+
+        Ok(messages)
+    }
+
+    /// The conversation a message belongs to
+    pub fn get_conversation_id(&mut self, id: &str) -> Result<Option<String>, MessageError> {
+        let conn = self.pool.get()?;
+        let conversation_id = conn.query_row(
+            &format!("SELECT conversation_id FROM {} WHERE id = ?1", Self::TABLE_NAME),
+            params![id],
+            |row| row.get(0),
+        )?;
+        Ok(conversation_id)
+    }
+
+    /// All messages of a conversation, oldest first
+    pub fn list_by_conversation(&mut self, conversation_id: &str) -> Result<Vec<Message>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, text, reasoning, sender, timestamp, tokens, embedding FROM {} WHERE conversation_id = ?1 ORDER BY timestamp, rowid",
+            Self::TABLE_NAME
+        ))?;
+
+        let messages = stmt
+            .query_map(params![conversation_id], |row| {
+				let sender_str: String = row.get(3)?;
+				let sender = MessageRole::try_from(sender_str)
+					.map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+                Ok(Message {
+                    id: row.get(0)?,
+                    text: row.get(1)?,
+                    reasoning: row.get(2)?,
+                    sender,
+                    timestamp: row.get(4)?,
+                    tokens: row.get(5)?,
+                    embedding: row.get(6)?,
+                    compaction: None,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()
+            .map_err(MessageError::from)?;
+
+        Ok(messages)
+    }
+
+    /// The messages on the path from the root of the thread down to `leaf_id`,
+    /// walking the parent links in `thread_table_name`
+    pub fn get_branch(&mut self, leaf_id: &str, thread_table_name: &str) -> Result<Vec<Message>, MessageError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "WITH RECURSIVE branch (id, depth) AS (
+				SELECT ?1, 0
+				UNION ALL
+				SELECT t.parent_id, branch.depth + 1 FROM {1} t JOIN branch ON t.id = branch.id
+				WHERE t.parent_id IS NOT NULL
+			)
+			SELECT m.id, m.text, m.reasoning, m.sender, m.timestamp, m.tokens, m.embedding
+			FROM branch JOIN {0} m ON m.id = branch.id
+			ORDER BY branch.depth DESC",
+            Self::TABLE_NAME,
+            thread_table_name
+        ))?;
+
+        let messages = stmt
+            .query_map(params![leaf_id], |row| {
+				let sender_str: String = row.get(3)?;
+				let sender = MessageRole::try_from(sender_str)
+					.map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+                Ok(Message {
+                    id: row.get(0)?,
+                    text: row.get(1)?,
+                    reasoning: row.get(2)?,
+                    sender,
+                    timestamp: row.get(4)?,
+                    tokens: row.get(5)?,
+                    embedding: row.get(6)?,
+                    compaction: None,
                 })
             })?
             .collect::<Result<Vec<_>, rusqlite::Error>>()
             .map_err(MessageError::from)?;
 
+        if messages.is_empty() {
+            return Err(MessageError::Database(rusqlite::Error::QueryReturnedNoRows));
+        }
         Ok(messages)
     }
 
@@ -148,6 +272,21 @@ impl Messages {
         Ok(())
     }
 
+    /// Append text and reasoning to the end of a message.
+    pub fn append(&mut self, id: &str, text: &str, reasoning: &str) -> Result<(), MessageError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "UPDATE {} SET text = text || ?2,
+					reasoning = CASE WHEN ?3 = '' THEN reasoning ELSE COALESCE(reasoning, '') || ?3 END
+				WHERE id = ?1",
+                Self::TABLE_NAME
+            ),
+            params![id, text, reasoning],
+        )?;
+        Ok(())
+    }
+
     pub fn update_sender(&mut self, id: &str, sender: MessageRole) -> Result<(), MessageError> {
         let conn = self.pool.get()?;
         conn.execute(
@@ -162,6 +301,11 @@ impl Messages {
 
     pub fn delete(&mut self, id: &str) -> Result<(), MessageError> {
         let conn = self.pool.get()?;
+        Self::delete_on(&conn, id)
+    }
+
+    /// `delete` on a given connection, so it can be part of a transaction.
+    pub fn delete_on(conn: &Connection, id: &str) -> Result<(), MessageError> {
         conn.execute(
             &format!(
                 "DELETE FROM {} WHERE id = ?1",
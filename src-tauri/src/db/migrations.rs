@@ -0,0 +1,234 @@
+use super::types::MigrationError;
+use super::DbPool;
+use rusqlite::{params, Transaction};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A schema change that brings the database to `version`
+struct Migration {
+    version: i64,
+    description: &'static str,
+    apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Migrations in the order they are applied. Version 1 is the schema the table
+/// managers created before migrations existed. New tables are still created by
+/// their managers with `CREATE TABLE IF NOT EXISTS`, which reaches existing
+/// databases on its own; changes to tables that already exist are added here
+/// rather than to their `CREATE TABLE` statements, so they reach existing
+/// databases too.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Baseline schema created by the table managers",
+        apply: |_| Ok(()),
+    },
+    Migration {
+        version: 2,
+        description: "Record the owning conversation of every message",
+        // Messages no conversation reaches keep a NULL conversation_id, for the
+        // integrity check to report
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE messages ADD COLUMN conversation_id TEXT REFERENCES conversations (id) ON DELETE CASCADE;
+				CREATE INDEX IF NOT EXISTS idx_messages_conversation_id ON messages (conversation_id);
+				WITH RECURSIVE owned (id, conversation_id) AS (
+					SELECT entry_message_id, id FROM conversations WHERE entry_message_id IS NOT NULL
+					UNION
+					SELECT threads.id, owned.conversation_id FROM threads JOIN owned ON threads.parent_id = owned.id
+				)
+				UPDATE messages SET conversation_id = (SELECT conversation_id FROM owned WHERE owned.id = messages.id);",
+            )
+        },
+    },
+    Migration {
+        version: 3,
+        description: "Index thread relations by parent",
+        apply: |tx| {
+            tx.execute_batch(
+                "CREATE INDEX IF NOT EXISTS idx_threads_parent_id ON threads (parent_id);",
+            )
+        },
+    },
+    Migration {
+        version: 4,
+        description: "Remember the active branch of each conversation",
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN active_leaf_id TEXT REFERENCES messages (id) ON DELETE SET NULL;",
+            )
+        },
+    },
+    Migration {
+        version: 5,
+        description: "Allow a single parent per message",
+        // A trigger rather than a unique index, so databases that already hold
+        // several parents for a message still open and can be repaired
+        apply: |tx| {
+            tx.execute_batch(
+                "CREATE TRIGGER IF NOT EXISTS threads_single_parent BEFORE INSERT ON threads
+				WHEN NEW.id = NEW.parent_id OR EXISTS (SELECT 1 FROM threads WHERE id = NEW.id)
+				BEGIN
+					SELECT RAISE(ABORT, 'a message can only have one parent');
+				END;",
+            )
+        },
+    },
+    Migration {
+        version: 6,
+        description: "Record where forked conversations came from",
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN forked_from_conversation_id TEXT REFERENCES conversations (id) ON DELETE SET NULL;
+				ALTER TABLE conversations ADD COLUMN forked_from_message_id TEXT REFERENCES messages (id) ON DELETE SET NULL;",
+            )
+        },
+    },
+    Migration {
+        version: 7,
+        description: "Track when conversations were created and last changed",
+        // Triggers keep updated_at current however a message is added or edited
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN created_at INTEGER;
+				ALTER TABLE conversations ADD COLUMN updated_at INTEGER;
+				UPDATE conversations SET
+					created_at = COALESCE((SELECT MIN(timestamp) FROM messages WHERE conversation_id = conversations.id), CAST(strftime('%s', 'now') AS INTEGER)),
+					updated_at = COALESCE((SELECT MAX(timestamp) FROM messages WHERE conversation_id = conversations.id), CAST(strftime('%s', 'now') AS INTEGER));
+				CREATE INDEX IF NOT EXISTS idx_conversations_created_at ON conversations (created_at);
+				CREATE INDEX IF NOT EXISTS idx_conversations_updated_at ON conversations (updated_at);
+				CREATE TRIGGER IF NOT EXISTS messages_insert_touch_conversation AFTER INSERT ON messages
+				BEGIN
+					UPDATE conversations SET updated_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = NEW.conversation_id;
+				END;
+				CREATE TRIGGER IF NOT EXISTS messages_update_touch_conversation AFTER UPDATE OF text, reasoning ON messages
+				BEGIN
+					UPDATE conversations SET updated_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = NEW.conversation_id;
+				END;",
+            )
+        },
+    },
+    Migration {
+        version: 8,
+        description: "Pin, archive and file conversations in folders",
+        apply: |tx| {
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+				ALTER TABLE conversations ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+				ALTER TABLE conversations ADD COLUMN folder_id TEXT REFERENCES folders (id) ON DELETE SET NULL;
+				CREATE INDEX IF NOT EXISTS idx_conversations_folder_id ON conversations (folder_id);
+				CREATE INDEX IF NOT EXISTS idx_folders_parent_id ON folders (parent_id);",
+            )
+        },
+    },
+    Migration {
+        version: 9,
+        description: "Keep the names of existing conversations out of title generation",
+        apply: |tx| {
+            tx.execute_batch(
+                "INSERT OR IGNORE INTO conversation_titles (conversation_id, source)
+					SELECT id, 'user' FROM conversations;",
+            )
+        },
+    },
+    Migration {
+        version: 10,
+        description: "Keep a single parent per message when relations are updated",
+        apply: |tx| {
+            tx.execute_batch(
+                "CREATE TRIGGER IF NOT EXISTS threads_single_parent_update BEFORE UPDATE OF id, parent_id ON threads
+				WHEN NEW.id = NEW.parent_id
+					OR (NEW.id <> OLD.id AND EXISTS (SELECT 1 FROM threads WHERE id = NEW.id))
+				BEGIN
+					SELECT RAISE(ABORT, 'a message can only have one parent');
+				END;",
+            )
+        },
+    },
+];
+
+/// Schema version written by this version of the app
+pub const LATEST_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Tracks the schema version of a database in `PRAGMA user_version`.
+pub struct Migrator {
+    pool: DbPool,
+    db_path: PathBuf,
+    version: i64,
+    has_data: bool,
+}
+
+impl Migrator {
+    /// Reads the schema version of the database, refusing one written by a
+    /// newer version of the app. Call before the table managers touch it.
+    pub fn open(pool: DbPool, db_path: &Path) -> Result<Self, MigrationError> {
+        let conn = pool.get()?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > LATEST_VERSION {
+            return Err(MigrationError::NewerVersion {
+                found: version,
+                supported: LATEST_VERSION,
+            });
+        }
+        let has_data: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(Self {
+            pool,
+            db_path: db_path.to_path_buf(),
+            version,
+            has_data,
+        })
+    }
+
+    /// Applies the pending migrations in one transaction, backing up the
+    /// database first if it already held data. Returns the new version.
+    pub fn migrate(&self) -> Result<i64, MigrationError> {
+        let pending: Vec<_> = MIGRATIONS
+            .iter()
+            .filter(|m| m.version > self.version)
+            .collect();
+        if pending.is_empty() {
+            return Ok(self.version);
+        }
+        if self.has_data {
+            self.backup()?;
+        }
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for migration in pending {
+            log::info!(
+                "Migrating database to version {}: {}",
+                migration.version,
+                migration.description
+            );
+            (migration.apply)(&tx).map_err(|source| MigrationError::Failed {
+                version: migration.version,
+                source,
+            })?;
+        }
+        tx.pragma_update(None, "user_version", LATEST_VERSION)?;
+        tx.commit()?;
+        Ok(LATEST_VERSION)
+    }
+
+    /// Writes a copy of the database next to it, named after its current version
+    fn backup(&self) -> Result<PathBuf, MigrationError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut name = self.db_path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".v{}-{}.bak", self.version, timestamp));
+        let backup_path = self.db_path.with_file_name(name);
+
+        let conn = self.pool.get()?;
+        conn.execute("VACUUM INTO ?1", params![backup_path.to_string_lossy()])?;
+        log::info!("Backed up database to {:?}", backup_path);
+        Ok(backup_path)
+    }
+}
@@ -7,6 +7,15 @@ pub mod messages;
 pub mod chat;
 pub mod conversations;
 pub mod types;
+pub mod attachments;
+pub mod generations;
+pub mod fallbacks;
+pub mod titles;
+pub mod compactions;
+pub mod migrations;
+pub mod integrity;
+pub mod folders;
+pub mod tags;
 
 pub type DbPool = Arc<Pool<SqliteConnectionManager>>;
 
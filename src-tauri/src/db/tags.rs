@@ -0,0 +1,214 @@
+use rusqlite::params;
+use super::DbPool;
+use super::conversations::Conversations;
+use super::messages::Messages;
+use super::types::{Message, MessageRole, Tag, TagError, TaggedMessage};
+use crate::utils::get_uuid_v4;
+
+/// Tags and the conversations and messages they are attached to
+pub struct Tags {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Tags {
+    pub const TABLE_NAME: &'static str = "tags";
+    pub const CONVERSATION_TABLE_NAME: &'static str = "conversation_tags";
+    pub const MESSAGE_TABLE_NAME: &'static str = "message_tags";
+
+    pub fn new(pool: DbPool, conversation_table_name: &str, message_table_name: &str) -> Result<Self, TagError> {
+        let conn = pool.get()?;
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {0} (
+				id TEXT PRIMARY KEY,
+				name TEXT NOT NULL UNIQUE COLLATE NOCASE
+			);
+			CREATE TABLE IF NOT EXISTS {1} (
+				conversation_id TEXT NOT NULL,
+				tag_id TEXT NOT NULL,
+				PRIMARY KEY (conversation_id, tag_id),
+				FOREIGN KEY (conversation_id) REFERENCES {3} (id) ON DELETE CASCADE,
+				FOREIGN KEY (tag_id) REFERENCES {0} (id) ON DELETE CASCADE
+			);
+			CREATE INDEX IF NOT EXISTS idx_{1}_tag_id ON {1} (tag_id);
+			CREATE TABLE IF NOT EXISTS {2} (
+				message_id TEXT NOT NULL,
+				tag_id TEXT NOT NULL,
+				PRIMARY KEY (message_id, tag_id),
+				FOREIGN KEY (message_id) REFERENCES {4} (id) ON DELETE CASCADE,
+				FOREIGN KEY (tag_id) REFERENCES {0} (id) ON DELETE CASCADE
+			);
+			CREATE INDEX IF NOT EXISTS idx_{2}_tag_id ON {2} (tag_id);",
+            Self::TABLE_NAME,
+            Self::CONVERSATION_TABLE_NAME,
+            Self::MESSAGE_TABLE_NAME,
+            conversation_table_name,
+            message_table_name
+        ))?;
+
+        Ok(Self { pool })
+    }
+
+    /// The tag with this name, ignoring case, created if there is none yet
+    pub fn get_or_create(&mut self, name: &str) -> Result<Tag, TagError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(TagError::EmptyName);
+        }
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (id, name) VALUES (?1, ?2) ON CONFLICT (name) DO NOTHING",
+                Self::TABLE_NAME
+            ),
+            params![get_uuid_v4(), name],
+        )?;
+        let tag = conn.query_row(
+            &format!("SELECT id, name FROM {} WHERE name = ?1", Self::TABLE_NAME),
+            params![name],
+            |row| Ok(Tag { id: row.get(0)?, name: row.get(1)? }),
+        )?;
+        Ok(tag)
+    }
+
+    /// Every tag, sorted by name
+    pub fn list(&mut self) -> Result<Vec<Tag>, TagError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, name FROM {} ORDER BY name",
+            Self::TABLE_NAME
+        ))?;
+
+        let tags = stmt
+            .query_map([], |row| Ok(Tag { id: row.get(0)?, name: row.get(1)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Delete a tag, removing it from every conversation and message
+    pub fn delete(&mut self, id: &str) -> Result<(), TagError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Self::TABLE_NAME),
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_to_conversation(&mut self, conversation_id: &str, tag_id: &str) -> Result<(), TagError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO {} (conversation_id, tag_id) VALUES (?1, ?2)",
+                Self::CONVERSATION_TABLE_NAME
+            ),
+            params![conversation_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_from_conversation(&mut self, conversation_id: &str, tag_id: &str) -> Result<(), TagError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE conversation_id = ?1 AND tag_id = ?2",
+                Self::CONVERSATION_TABLE_NAME
+            ),
+            params![conversation_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_by_conversation(&mut self, conversation_id: &str) -> Result<Vec<Tag>, TagError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT t.id, t.name FROM {} t JOIN {} ct ON ct.tag_id = t.id
+				WHERE ct.conversation_id = ?1 ORDER BY t.name",
+            Self::TABLE_NAME,
+            Self::CONVERSATION_TABLE_NAME
+        ))?;
+
+        let tags = stmt
+            .query_map(params![conversation_id], |row| Ok(Tag { id: row.get(0)?, name: row.get(1)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    pub fn add_to_message(&mut self, message_id: &str, tag_id: &str) -> Result<(), TagError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO {} (message_id, tag_id) VALUES (?1, ?2)",
+                Self::MESSAGE_TABLE_NAME
+            ),
+            params![message_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_from_message(&mut self, message_id: &str, tag_id: &str) -> Result<(), TagError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE message_id = ?1 AND tag_id = ?2",
+                Self::MESSAGE_TABLE_NAME
+            ),
+            params![message_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_by_message(&mut self, message_id: &str) -> Result<Vec<Tag>, TagError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT t.id, t.name FROM {} t JOIN {} mt ON mt.tag_id = t.id
+				WHERE mt.message_id = ?1 ORDER BY t.name",
+            Self::TABLE_NAME,
+            Self::MESSAGE_TABLE_NAME
+        ))?;
+
+        let tags = stmt
+            .query_map(params![message_id], |row| Ok(Tag { id: row.get(0)?, name: row.get(1)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Messages carrying `tag_id`, or any tag if `None`, across all
+    /// conversations, newest first
+    pub fn list_tagged_messages(&mut self, tag_id: Option<&str>) -> Result<Vec<TaggedMessage>, TagError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT m.id, m.text, m.reasoning, m.sender, m.timestamp, m.tokens, m.embedding, c.id, c.name
+				FROM {0} m JOIN {1} c ON c.id = m.conversation_id
+				WHERE EXISTS (SELECT 1 FROM {2} mt WHERE mt.message_id = m.id AND (?1 IS NULL OR mt.tag_id = ?1))
+				ORDER BY m.timestamp DESC, m.rowid DESC",
+            Messages::TABLE_NAME,
+            Conversations::TABLE_NAME,
+            Self::MESSAGE_TABLE_NAME
+        ))?;
+
+        let messages = stmt
+            .query_map(params![tag_id], |row| {
+                let sender_str: String = row.get(3)?;
+                let sender = MessageRole::try_from(sender_str)
+                    .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+                Ok(TaggedMessage {
+                    conversation_id: row.get(7)?,
+                    conversation_name: row.get(8)?,
+                    message: Message {
+                        id: row.get(0)?,
+                        text: row.get(1)?,
+                        reasoning: row.get(2)?,
+                        sender,
+                        timestamp: row.get(4)?,
+                        tokens: row.get(5)?,
+                        embedding: row.get(6)?,
+                        compaction: None,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(messages)
+    }
+}
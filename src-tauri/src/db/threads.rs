@@ -1,4 +1,4 @@
-use rusqlite::params;
+use rusqlite::{params, Connection};
 use super::DbPool;
 use super::types::ThreadError;
 
@@ -35,9 +35,17 @@ impl Threads {
         Ok(Self { pool })
     }
 
-	/// Add a new thread relation.
+	/// Add a new thread relation. A message can only have one parent.
     pub fn add(&mut self, message_id: &str, parent_id: Option<&str>) -> Result<(), ThreadError> {
         let conn = self.pool.get()?;
+        Self::add_on(&conn, message_id, parent_id)
+    }
+
+    /// `add` on a given connection, so it can be part of a transaction.
+    pub fn add_on(conn: &Connection, message_id: &str, parent_id: Option<&str>) -> Result<(), ThreadError> {
+        if Self::exists_node_on(conn, message_id)? {
+            return Err(ThreadError::MultipleParents(message_id.to_string()));
+        }
         conn.execute(
             &format!(
                 "INSERT INTO {} (id, parent_id) VALUES (?1, ?2)",
@@ -52,23 +60,43 @@ impl Threads {
     pub fn add_batch(&mut self, relations: &[(&str, &str)]) -> Result<(), ThreadError> {
         let mut conn = self.pool.get().map_err(ThreadError::Pool)?;
         let tx = conn.transaction()?;
-        {
-            let mut stmt = tx.prepare(&format!(
-                "INSERT INTO {} (id, parent_id) VALUES (?1, ?2)",
-                Self::TABLE_NAME
-            ))?;
+        let relations: Vec<_> = relations
+            .iter()
+            .map(|(message_id, parent_id)| (*message_id, Some(*parent_id)))
+            .collect();
+        Self::add_batch_on(&tx, &relations)?;
+        tx.commit()?;
+        Ok(())
+    }
 
-            for (message_id, parent_id) in relations {
-                stmt.execute(params![message_id, parent_id])?;
-            }
+	/// Add thread relations on a given connection, so they can be part of a
+	/// transaction. Messages without a parent are roots.
+    pub fn add_batch_on(conn: &Connection, relations: &[(&str, Option<&str>)]) -> Result<(), ThreadError> {
+        let mut stmt = conn.prepare(&format!(
+            "INSERT INTO {} (id, parent_id) VALUES (?1, ?2)",
+            Self::TABLE_NAME
+        ))?;
+
+        for (message_id, parent_id) in relations {
+            stmt.execute(params![message_id, parent_id])?;
         }
-        tx.commit()?;
         Ok(())
     }
 
-	/// Update the parent of a thread relation.
+	/// Update the parent of a thread relation, refusing to move a message
+	/// under itself or one of its descendants.
 	pub fn update_parent(&mut self, message_id: &str, new_parent_id: Option<&str>) -> Result<(), ThreadError> {
 		let conn = self.pool.get()?;
+		Self::update_parent_on(&conn, message_id, new_parent_id)
+	}
+
+	/// `update_parent` on a given connection, so it can be part of a transaction.
+	pub fn update_parent_on(conn: &Connection, message_id: &str, new_parent_id: Option<&str>) -> Result<(), ThreadError> {
+		if let Some(new_parent_id) = new_parent_id {
+			if Self::is_ancestor_on(conn, message_id, new_parent_id)? {
+				return Err(ThreadError::Cycle(message_id.to_string()));
+			}
+		}
 		conn.execute(
 			&format!(
 				"UPDATE {} SET parent_id = ?1 WHERE id = ?2",
@@ -178,6 +206,50 @@ impl Threads {
         Ok(parent_id)
     }
 
+	/// Get every relation in the subtree under `root_id`, including the root,
+	/// in insertion order.
+	pub fn get_subtree(&mut self, root_id: &str) -> Result<Vec<(String, Option<String>)>, ThreadError> {
+		let conn = self.pool.get()?;
+		let mut stmt = conn.prepare(&format!(
+			"WITH RECURSIVE subtree (seq, id, parent_id) AS (
+				SELECT rowid, id, parent_id FROM {0} WHERE id = ?1
+				UNION
+				SELECT t.rowid, t.id, t.parent_id FROM {0} t JOIN subtree ON t.parent_id = subtree.id
+			)
+			SELECT id, parent_id FROM subtree ORDER BY seq",
+			Self::TABLE_NAME
+		))?;
+
+		let relations = stmt
+			.query_map(params![root_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(relations)
+	}
+
+	/// Check if `ancestor_id` is `message_id` or lies on the path from the root to it.
+	pub fn is_ancestor(&mut self, ancestor_id: &str, message_id: &str) -> Result<bool, ThreadError> {
+		let conn = self.pool.get()?;
+		Self::is_ancestor_on(&conn, ancestor_id, message_id)
+	}
+
+	fn is_ancestor_on(conn: &Connection, ancestor_id: &str, message_id: &str) -> Result<bool, ThreadError> {
+		let is_ancestor: bool = conn.query_row(
+			&format!(
+				"WITH RECURSIVE ancestors (id) AS (
+					SELECT ?2
+					UNION
+					SELECT t.parent_id FROM {} t JOIN ancestors ON t.id = ancestors.id
+					WHERE t.parent_id IS NOT NULL
+				)
+				SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?1)",
+				Self::TABLE_NAME
+			),
+			params![ancestor_id, message_id],
+			|row| row.get(0),
+		)?;
+		Ok(is_ancestor)
+	}
+
 	/// Check if a thread relation exists with a specific message and parent.
     pub fn exists(&mut self, message_id: &str, parent_id: &str) -> Result<bool, ThreadError> {
         let conn = self.pool.get()?;
@@ -195,6 +267,10 @@ impl Threads {
 	/// Check if a specific message exists in the thread.
     pub fn exists_node(&mut self, message_id: &str) -> Result<bool, ThreadError> {
         let conn = self.pool.get()?;
+        Self::exists_node_on(&conn, message_id)
+    }
+
+    fn exists_node_on(conn: &Connection, message_id: &str) -> Result<bool, ThreadError> {
         let exists: bool = conn.query_row(
             &format!(
                 "SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)",
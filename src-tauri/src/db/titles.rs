@@ -0,0 +1,61 @@
+use rusqlite::params;
+use super::DbPool;
+use super::types::{ConversationError, TitleSource};
+
+/// Records whether a conversation was named by the user or by a model, so
+/// generated titles never replace a name the user chose.
+pub struct Titles {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl Titles {
+    pub const TABLE_NAME: &'static str = "conversation_titles";
+
+    pub fn new(pool: DbPool, conversation_table_name: &str) -> Result<Self, ConversationError> {
+        let conn = pool.get()?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+					conversation_id TEXT PRIMARY KEY,
+					source TEXT NOT NULL,
+					FOREIGN KEY (conversation_id) REFERENCES {} (id) ON DELETE CASCADE
+				)",
+                Self::TABLE_NAME,
+                conversation_table_name
+            ),
+            [],
+        )?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn set_source(&mut self, conversation_id: &str, source: TitleSource) -> Result<(), ConversationError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (conversation_id, source) VALUES (?1, ?2)",
+                Self::TABLE_NAME
+            ),
+            params![conversation_id, source.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// `None` while the conversation still has the name it was created with
+    pub fn get_source(&mut self, conversation_id: &str) -> Result<Option<TitleSource>, ConversationError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT source FROM {} WHERE conversation_id = ?1",
+            Self::TABLE_NAME
+        ))?;
+
+        let result = stmt.query_row(params![conversation_id], |row| row.get::<_, String>(0));
+        match result {
+            Ok(source) if source == "user" => Ok(Some(TitleSource::User)),
+            Ok(_) => Ok(Some(TitleSource::Generated)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -21,6 +21,24 @@ pub enum ChatError {
     Thread(#[from] ThreadError),
 	#[error("Chat Conversation error: {0}")]
 	Conversation(#[from] ConversationError),
+    #[error("Chat Migration error: {0}")]
+    Migration(#[from] MigrationError),
+    #[error("Chat Folder error: {0}")]
+    Folder(#[from] FolderError),
+    #[error("Chat Tag error: {0}")]
+    Tag(#[from] TagError),
+}
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("Database error in Migration: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Database schema version {found} is newer than the supported version {supported}")]
+    NewerVersion { found: i64, supported: i64 },
+    #[error("Migration to schema version {version} failed: {source}")]
+    Failed { version: i64, source: rusqlite::Error },
 }
 
 
@@ -40,6 +58,125 @@ pub struct Conversation {
     pub name: String,
     pub description: Option<String>,
     pub entry_message_id: Option<String>,
+    /// Last message of the branch the user was on
+    pub active_leaf_id: Option<String>,
+    /// Conversation and message this one was forked from
+    pub forked_from_conversation_id: Option<String>,
+    pub forked_from_message_id: Option<String>,
+    /// Unix time in seconds
+    pub created_at: i64,
+    /// Unix time in seconds of the last message added or edited
+    pub updated_at: i64,
+    /// Pinned conversations are listed before the others
+    pub pinned: bool,
+    pub archived: bool,
+    pub folder_id: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum FolderError {
+    #[error("Database error in Folder: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Folder {0} not found")]
+    NotFound(String),
+    #[error("Moving folder {0} there would create a cycle")]
+    Cycle(String),
+}
+
+/// A folder of conversations. Folders nest through `parent_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    /// Unix time in seconds
+    pub created_at: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("Database error in Tag: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Tag name cannot be empty")]
+    EmptyName,
+}
+
+/// A label on conversations and messages. Names are unique, ignoring case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+}
+
+/// A tagged message together with the conversation it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaggedMessage {
+    pub conversation_id: String,
+    pub conversation_name: String,
+    pub message: Message,
+}
+
+/// Which folder conversations are listed from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderFilter {
+    /// Conversations in any folder or none
+    #[default]
+    Any,
+    /// Conversations that are not in a folder
+    Root,
+    /// Conversations directly in the given folder
+    Folder(String),
+}
+
+/// What conversations are listed by
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationSort {
+    Name,
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+}
+
+/// How to sort, page and filter a list of conversations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationQuery {
+    pub sort: ConversationSort,
+    /// Oldest or alphabetically first at the top; newest first otherwise
+    pub ascending: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Only conversations whose name or description contains this text
+    pub filter: Option<String>,
+    pub folder: FolderFilter,
+    /// List archived conversations instead of the others
+    pub archived: bool,
+    /// Only conversations carrying every one of these tag ids
+    pub tags: Vec<String>,
+}
+
+/// Where the name of a conversation came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TitleSource {
+    #[serde(rename = "user")]
+    User,
+    #[serde(rename = "generated")]
+    Generated,
+}
+
+impl std::fmt::Display for TitleSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TitleSource::User => write!(f, "user"),
+            TitleSource::Generated => write!(f, "generated"),
+        }
+    }
 }
 
 
@@ -53,6 +190,28 @@ pub enum ThreadError {
     InvalidRelation,
 	#[error("Invalid thread relation in batch operation at index {0}")]
 	InvalidRelationBatch(usize),
+	#[error("Message {0} already has a parent")]
+	MultipleParents(String),
+	#[error("Moving message {0} there would create a cycle")]
+	Cycle(String),
+}
+
+/// Problems found in the message tree by an integrity check
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Messages with more than one parent relation
+    pub multiple_parents: Vec<String>,
+    /// Messages whose parent no longer exists, or that lost their relation
+    /// while not being the entry of a conversation
+    pub dangling_parents: Vec<String>,
+    /// Messages that do not belong to an existing conversation
+    pub without_conversation: Vec<String>,
+    pub repaired: bool,
+    /// Messages the repair moved under the entry message of their conversation,
+    /// or made its entry
+    pub reattached: Vec<String>,
+    /// Messages the repair deleted because no conversation reaches them
+    pub deleted: Vec<String>,
 }
 
 
@@ -86,13 +245,24 @@ impl std::fmt::Display for MessageRole {
     }
 }
 
+impl MessageRole {
+    /// Role name used in OpenAI-compatible chat requests
+    pub fn api_role(&self) -> &'static str {
+        match self {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::System => "system",
+        }
+    }
+}
+
 impl TryFrom<String> for MessageRole {
     type Error = MessageError;
 
     fn try_from(s: String) -> Result<Self, Self::Error> {
         match s.as_str() {
             "user" => Ok(MessageRole::User),
-            "bot" => Ok(MessageRole::Assistant),
+            "bot" | "assistant" => Ok(MessageRole::Assistant),
             "system" => Ok(MessageRole::System),
             s => Err(MessageError::InvalidRole(s.to_string())),
         }
@@ -110,4 +280,48 @@ pub struct Message {
     pub tokens: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub embedding: Option<Vec<u8>>,
+    /// Summary that replaces the history above this message in requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compaction: Option<Compaction>,
+}
+
+/// Serialises binary data as a base64 string for the frontend.
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub message_id: String,
+    pub name: String,
+    pub mime_type: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Generation {
+    pub message_id: String,
+    pub provider: String,
+    pub model: String,
+    pub parameters: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compaction {
+    pub message_id: String,
+    pub summary: String,
+    pub provider: String,
+    pub model: String,
 }
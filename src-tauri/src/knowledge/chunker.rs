@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::types::{Chunk, KnowledgeError};
+
+/// File extensions that are read as plain text.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "md", "markdown", "txt", "rst", "rs", "ts", "tsx", "js", "jsx", "vue", "py", "go", "java",
+    "kt", "swift", "c", "h", "cpp", "hpp", "cs", "rb", "php", "sh", "sql", "toml", "json",
+    "yaml", "yml", "html", "css", "scss",
+];
+
+/// Directories that never contain documents worth indexing.
+const IGNORED_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "__pycache__"];
+
+fn is_supported(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => {
+            let ext = ext.to_lowercase();
+            ext == "pdf" || TEXT_EXTENSIONS.contains(&ext.as_str())
+        }
+        None => false,
+    }
+}
+
+/// Recursively collect every supported document under `root`, skipping hidden
+/// and build directories.
+pub fn collect_files(root: &Path) -> Result<Vec<PathBuf>, KnowledgeError> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                if !IGNORED_DIRS.contains(&name) {
+                    pending.push(path);
+                }
+            } else if is_supported(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Read a document as text, extracting the text layer of PDFs.
+pub fn read_document(path: &Path) -> Result<String, KnowledgeError> {
+    let is_pdf = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false);
+
+    if is_pdf {
+        pdf_extract::extract_text(path)
+            .map_err(|e| KnowledgeError::UnsupportedDocument(format!("{}: {}", path.display(), e)))
+    } else {
+        let bytes = fs::read(path)?;
+        String::from_utf8(bytes)
+            .map_err(|_| KnowledgeError::UnsupportedDocument(format!("{}: not valid UTF-8", path.display())))
+    }
+}
+
+/// Split text into chunks of at most `max_chars` characters along line
+/// boundaries. Consecutive chunks share `overlap_lines` lines, and a Markdown
+/// heading starts a new chunk once the current one is reasonably full.
+/// Line numbers are 1-based and inclusive.
+pub fn chunk_text(text: &str, max_chars: usize, overlap_lines: usize) -> Vec<Chunk> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        // Hard-wrap lines that would not fit in a chunk on their own
+        let chars: Vec<char> = line.chars().collect();
+        if chars.len() > max_chars {
+            for piece in chars.chunks(max_chars) {
+                lines.push((i + 1, piece.iter().collect()));
+            }
+        } else {
+            lines.push((i + 1, line.to_string()));
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<&(usize, String)> = Vec::new();
+    let mut current_len = 0;
+
+    for line in &lines {
+        let line_len = line.1.chars().count() + 1;
+        let is_heading = line.1.starts_with('#');
+        let overflow = current_len + line_len > max_chars;
+        let heading_break = is_heading && current_len > max_chars / 4;
+
+        if !current.is_empty() && (overflow || heading_break) {
+            chunks.push(make_chunk(&current));
+            let keep = if heading_break { 0 } else { overlap_lines.min(current.len()) };
+            current = current.split_off(current.len() - keep);
+            current_len = current.iter().map(|l| l.1.chars().count() + 1).sum();
+            // Drop the overlap again if it would not leave room for the new line
+            while !current.is_empty() && current_len + line_len > max_chars {
+                current_len -= current[0].1.chars().count() + 1;
+                current.remove(0);
+            }
+        }
+
+        current.push(line);
+        current_len += line_len;
+    }
+
+    if current.iter().any(|l| !l.1.trim().is_empty()) {
+        chunks.push(make_chunk(&current));
+    }
+
+    chunks.retain(|c| !c.text.trim().is_empty());
+    chunks
+}
+
+fn make_chunk(lines: &[&(usize, String)]) -> Chunk {
+    Chunk {
+        text: lines.iter().map(|l| l.1.as_str()).collect::<Vec<_>>().join("\n"),
+        start_line: lines.first().map(|l| l.0).unwrap_or(1),
+        end_line: lines.last().map(|l| l.0).unwrap_or(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {:02}", i)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn line_range(chunk: &Chunk) -> (usize, usize) {
+        (chunk.start_line, chunk.end_line)
+    }
+
+    #[test]
+    fn empty_input_has_no_chunks() {
+        assert!(chunk_text("", 100, 2).is_empty());
+        assert!(chunk_text("\n  \n\n", 100, 2).is_empty());
+    }
+
+    #[test]
+    fn short_input_is_one_chunk() {
+        let chunks = chunk_text("first\nsecond\nthird", 100, 2);
+        assert_eq!(
+            chunks,
+            vec![Chunk {
+                text: "first\nsecond\nthird".to_string(),
+                start_line: 1,
+                end_line: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn consecutive_chunks_share_overlap_lines() {
+        // Each line takes 7 characters plus its newline, so 5 lines fit in 40
+        let chunks = chunk_text(&numbered_lines(10), 40, 1);
+        let ranges: Vec<_> = chunks.iter().map(line_range).collect();
+        assert_eq!(ranges, vec![(1, 5), (5, 9), (9, 10)]);
+        assert_eq!(chunks[1].text, "line 05\nline 06\nline 07\nline 08\nline 09");
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 40));
+    }
+
+    #[test]
+    fn chunks_without_overlap_cover_each_line_once() {
+        let chunks = chunk_text(&numbered_lines(10), 40, 0);
+        let ranges: Vec<_> = chunks.iter().map(line_range).collect();
+        assert_eq!(ranges, vec![(1, 5), (6, 10)]);
+    }
+
+    #[test]
+    fn heading_starts_a_new_chunk_without_overlap() {
+        let text = "intro one\nintro two\nintro three\n# Heading\nbody";
+        let chunks = chunk_text(text, 100, 2);
+        let ranges: Vec<_> = chunks.iter().map(line_range).collect();
+        assert_eq!(ranges, vec![(1, 3), (4, 5)]);
+        assert!(chunks[1].text.starts_with("# Heading"));
+    }
+
+    #[test]
+    fn long_lines_are_wrapped_and_keep_their_line_number() {
+        let text = format!("short\n{}\nend", "x".repeat(25));
+        let chunks = chunk_text(&text, 10, 0);
+        let ranges: Vec<_> = chunks.iter().map(line_range).collect();
+        assert_eq!(ranges, vec![(1, 1), (2, 2), (2, 2), (2, 3)]);
+        assert_eq!(chunks[3].text, "xxxxx\nend");
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 10));
+    }
+}
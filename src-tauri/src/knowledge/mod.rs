@@ -0,0 +1,245 @@
+pub mod chunker;
+pub mod store;
+pub mod types;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::api;
+use crate::configs::model::ModelInfo;
+use crate::configs::provider::Provider;
+use crate::db::create_pool;
+use crate::scheduler::Priority;
+use crate::utils::compute_content_hash;
+use store::KnowledgeStore;
+use types::{Citation, IndexProgress, IndexReport, KnowledgeBaseInfo, KnowledgeError, RetrievedChunk};
+
+const CHUNK_MAX_CHARS: usize = 1500;
+const CHUNK_OVERLAP_LINES: usize = 2;
+const EMBEDDING_BATCH_SIZE: usize = 64;
+pub const DEFAULT_TOP_K: usize = 5;
+
+/// Folders of documents, chunked and embedded into `knowledge.db`, that
+/// conversations can retrieve context from.
+#[derive(Clone)]
+pub struct KnowledgeBase {
+    pub store: KnowledgeStore,
+}
+
+#[allow(unused)]
+impl KnowledgeBase {
+    pub fn new(app_handle: &AppHandle) -> Result<Self, KnowledgeError> {
+        let app_dir = app_handle
+            .path()
+            .app_data_dir()
+            .expect("Failed to get app data dir");
+        std::fs::create_dir_all(&app_dir)?;
+        let db_path = app_dir.join("knowledge.db");
+        let db_path = db_path.to_str().expect("Failed to reach database path");
+
+        let pool = create_pool(db_path);
+        Ok(Self {
+            store: KnowledgeStore::new(pool)?,
+        })
+    }
+
+    /// Bring the index of a knowledge base up to date with its source folders.
+    /// Documents whose content hash is unchanged are skipped, and documents that
+    /// disappeared from the sources are removed. Sources that cannot be read are
+    /// reported as failed and their documents are kept.
+    pub async fn index(
+        &mut self,
+        app_handle: &AppHandle,
+        knowledge_base_id: &str,
+        provider: &Provider,
+    ) -> Result<IndexReport, KnowledgeError> {
+        let base = self
+            .store
+            .get_base(knowledge_base_id)?
+            .ok_or_else(|| KnowledgeError::NotFound(knowledge_base_id.to_string()))?;
+        let dimensions = embedding_dimensions(provider, &base.embedding_model);
+
+        let mut report = IndexReport::default();
+        let mut files = Vec::new();
+        let mut failed_sources = Vec::new();
+        for source in &base.sources {
+            match chunker::collect_files(Path::new(source)) {
+                Ok(source_files) => files.extend(source_files),
+                Err(e) => {
+                    log::warn!("Failed to read source {}: {}", source, e);
+                    report.failed.push(source.clone());
+                    failed_sources.push(Path::new(source));
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let total = files.len();
+
+        for (done, file) in files.iter().enumerate() {
+            let path = file.to_string_lossy().to_string();
+            seen.insert(path.clone());
+            let _ = app_handle.emit(
+                "knowledge_index_progress",
+                IndexProgress {
+                    knowledge_base_id: knowledge_base_id.to_string(),
+                    path: path.clone(),
+                    done,
+                    total,
+                },
+            );
+
+            let content = match chunker::read_document(file) {
+                Ok(content) => content,
+                Err(e) => {
+                    log::warn!("Failed to read {}: {}", path, e);
+                    report.failed.push(path);
+                    continue;
+                }
+            };
+
+            let hash = compute_content_hash(&content);
+            if self.store.get_document_hash(knowledge_base_id, &path)?.as_deref() == Some(hash.as_str()) {
+                report.skipped += 1;
+                continue;
+            }
+
+            let chunks = chunker::chunk_text(&content, CHUNK_MAX_CHARS, CHUNK_OVERLAP_LINES);
+            let mut embedded = Vec::with_capacity(chunks.len());
+            for batch in chunks.chunks(EMBEDDING_BATCH_SIZE) {
+                let inputs = batch.iter().map(|c| c.text.clone()).collect();
+                let embeddings = api::create_embeddings(app_handle, provider, Priority::Background, &base.embedding_model, dimensions, inputs)
+                    .await
+                    .map_err(|e| KnowledgeError::Embedding(e.to_string()))?;
+                embedded.extend(batch.iter().cloned().zip(embeddings));
+            }
+
+            self.store.replace_document(knowledge_base_id, &path, &hash, &embedded)?;
+            report.indexed += 1;
+        }
+
+        for path in self.store.list_document_paths(knowledge_base_id)? {
+            // Keep the documents of sources that could not be read this time
+            let in_failed_source = failed_sources.iter().any(|source| Path::new(&path).starts_with(source));
+            if !seen.contains(&path) && !in_failed_source {
+                self.store.delete_document(knowledge_base_id, &path)?;
+                report.removed += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Retrieve the chunks most similar to `query` from every knowledge base
+    /// attached to a conversation.
+    pub async fn retrieve(
+        &mut self,
+        app_handle: &AppHandle,
+        providers: &[Provider],
+        conversation_id: &str,
+        query: &str,
+        top_k: usize,
+    ) -> Result<Vec<RetrievedChunk>, KnowledgeError> {
+        let bases = self.store.get_attached(conversation_id)?;
+        let mut query_embeddings: HashMap<(String, String), Vec<f32>> = HashMap::new();
+        let mut scored = Vec::new();
+
+        for base in bases {
+            let Some(provider) = providers.iter().find(|p| p.name == base.embedding_provider) else {
+                log::warn!("Embedding provider {} of knowledge base {} not found", base.embedding_provider, base.name);
+                continue;
+            };
+
+            let key = (base.embedding_provider.clone(), base.embedding_model.clone());
+            if !query_embeddings.contains_key(&key) {
+                let dimensions = embedding_dimensions(provider, &base.embedding_model);
+                let embedding = api::create_embeddings(app_handle, provider, Priority::Interactive, &base.embedding_model, dimensions, vec![query.to_string()])
+                    .await
+                    .map_err(|e| KnowledgeError::Embedding(e.to_string()))?
+                    .pop()
+                    .ok_or_else(|| KnowledgeError::Embedding("Empty embedding response".to_string()))?;
+                query_embeddings.insert(key.clone(), embedding);
+            }
+            let query_embedding = &query_embeddings[&key];
+
+            for stored in self.store.get_chunks(&base.id)? {
+                let score = cosine_similarity(query_embedding, &stored.embedding);
+                scored.push((score, base.id.clone(), stored));
+            }
+        }
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(top_k)
+            .enumerate()
+            .map(|(i, (score, knowledge_base_id, stored))| RetrievedChunk {
+                citation: Citation {
+                    index: i + 1,
+                    knowledge_base_id,
+                    path: stored.path,
+                    start_line: stored.chunk.start_line,
+                    end_line: stored.chunk.end_line,
+                    score,
+                },
+                text: stored.chunk.text,
+            })
+            .collect())
+    }
+
+    pub fn list_attached(&mut self, conversation_id: &str) -> Result<Vec<KnowledgeBaseInfo>, KnowledgeError> {
+        self.store.get_attached(conversation_id)
+    }
+}
+
+fn embedding_dimensions(provider: &Provider, model_name: &str) -> Option<u32> {
+    match provider.get_model(model_name).map(|m| &m.model_info) {
+        Some(ModelInfo::Embedding { parameters }) => parameters.embedding_dim.map(|d| d as u32),
+        _ => None,
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Insert the retrieved chunks as a system message right before the last user
+/// message, numbered so the model can cite them as `[n]`.
+pub fn inject_context(messages: &mut Vec<Value>, chunks: &[RetrievedChunk]) {
+    if chunks.is_empty() {
+        return;
+    }
+
+    let mut context = String::from(
+        "The following excerpts were retrieved from the user's documents. \
+		Use them when they are relevant and cite them as [n].\n",
+    );
+    for chunk in chunks {
+        context.push_str(&format!(
+            "\n[{}] {} (lines {}-{})\n{}\n",
+            chunk.citation.index,
+            chunk.citation.path,
+            chunk.citation.start_line,
+            chunk.citation.end_line,
+            chunk.text
+        ));
+    }
+
+    let position = messages
+        .iter()
+        .rposition(|m| m["role"] == "user")
+        .unwrap_or(messages.len());
+    messages.insert(position, json!({ "role": "system", "content": context }));
+}
@@ -0,0 +1,358 @@
+use rusqlite::{params, OptionalExtension};
+use crate::db::DbPool;
+use super::types::{Chunk, KnowledgeBaseInfo, KnowledgeError, StoredChunk};
+
+#[derive(Clone)]
+pub struct KnowledgeStore {
+    pool: DbPool,
+}
+
+#[allow(unused)]
+impl KnowledgeStore {
+    pub const BASES_TABLE_NAME: &'static str = "knowledge_bases";
+    pub const SOURCES_TABLE_NAME: &'static str = "knowledge_sources";
+    pub const DOCUMENTS_TABLE_NAME: &'static str = "knowledge_documents";
+    pub const CHUNKS_TABLE_NAME: &'static str = "knowledge_chunks";
+    pub const ATTACHMENTS_TABLE_NAME: &'static str = "conversation_knowledge";
+
+    pub fn new(pool: DbPool) -> Result<Self, KnowledgeError> {
+        let conn = pool.get()?;
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {bases} (
+				id TEXT PRIMARY KEY,
+				name TEXT NOT NULL,
+				embedding_provider TEXT NOT NULL,
+				embedding_model TEXT NOT NULL
+			);
+			CREATE TABLE IF NOT EXISTS {sources} (
+				knowledge_base_id TEXT NOT NULL,
+				path TEXT NOT NULL,
+				PRIMARY KEY (knowledge_base_id, path),
+				FOREIGN KEY (knowledge_base_id) REFERENCES {bases}(id) ON DELETE CASCADE
+			);
+			CREATE TABLE IF NOT EXISTS {documents} (
+				id INTEGER PRIMARY KEY AUTOINCREMENT,
+				knowledge_base_id TEXT NOT NULL,
+				path TEXT NOT NULL,
+				content_hash TEXT NOT NULL,
+				UNIQUE (knowledge_base_id, path),
+				FOREIGN KEY (knowledge_base_id) REFERENCES {bases}(id) ON DELETE CASCADE
+			);
+			CREATE TABLE IF NOT EXISTS {chunks} (
+				document_id INTEGER NOT NULL,
+				chunk_index INTEGER NOT NULL,
+				start_line INTEGER NOT NULL,
+				end_line INTEGER NOT NULL,
+				text TEXT NOT NULL,
+				embedding BLOB NOT NULL,
+				PRIMARY KEY (document_id, chunk_index),
+				FOREIGN KEY (document_id) REFERENCES {documents}(id) ON DELETE CASCADE
+			);
+			CREATE TABLE IF NOT EXISTS {attachments} (
+				conversation_id TEXT NOT NULL,
+				knowledge_base_id TEXT NOT NULL,
+				PRIMARY KEY (conversation_id, knowledge_base_id),
+				FOREIGN KEY (knowledge_base_id) REFERENCES {bases}(id) ON DELETE CASCADE
+			);",
+            bases = Self::BASES_TABLE_NAME,
+            sources = Self::SOURCES_TABLE_NAME,
+            documents = Self::DOCUMENTS_TABLE_NAME,
+            chunks = Self::CHUNKS_TABLE_NAME,
+            attachments = Self::ATTACHMENTS_TABLE_NAME,
+        ))?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn create_base(&mut self, id: &str, name: &str, embedding_provider: &str, embedding_model: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT INTO {} (id, name, embedding_provider, embedding_model) VALUES (?1, ?2, ?3, ?4)",
+                Self::BASES_TABLE_NAME
+            ),
+            params![id, name, embedding_provider, embedding_model],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_base(&mut self, id: &str) -> Result<Option<KnowledgeBaseInfo>, KnowledgeError> {
+        let conn = self.pool.get()?;
+        let base = conn
+            .query_row(
+                &format!(
+                    "SELECT id, name, embedding_provider, embedding_model FROM {} WHERE id = ?1",
+                    Self::BASES_TABLE_NAME
+                ),
+                params![id],
+                |row| {
+                    Ok(KnowledgeBaseInfo {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        embedding_provider: row.get(2)?,
+                        embedding_model: row.get(3)?,
+                        sources: vec![],
+                    })
+                },
+            )
+            .optional()?;
+        std::mem::drop(conn);
+
+        match base {
+            Some(mut base) => {
+                base.sources = self.get_sources(&base.id)?;
+                Ok(Some(base))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_bases(&mut self) -> Result<Vec<KnowledgeBaseInfo>, KnowledgeError> {
+        let ids = {
+            let conn = self.pool.get()?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id FROM {} ORDER BY name ASC",
+                Self::BASES_TABLE_NAME
+            ))?;
+            let ids = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+            ids
+        };
+
+        let mut bases = Vec::new();
+        for id in ids {
+            if let Some(base) = self.get_base(&id)? {
+                bases.push(base);
+            }
+        }
+        Ok(bases)
+    }
+
+    pub fn delete_base(&mut self, id: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Self::BASES_TABLE_NAME),
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_source(&mut self, knowledge_base_id: &str, path: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO {} (knowledge_base_id, path) VALUES (?1, ?2)",
+                Self::SOURCES_TABLE_NAME
+            ),
+            params![knowledge_base_id, path],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_source(&mut self, knowledge_base_id: &str, path: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE knowledge_base_id = ?1 AND path = ?2",
+                Self::SOURCES_TABLE_NAME
+            ),
+            params![knowledge_base_id, path],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_sources(&mut self, knowledge_base_id: &str) -> Result<Vec<String>, KnowledgeError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT path FROM {} WHERE knowledge_base_id = ?1 ORDER BY path ASC",
+            Self::SOURCES_TABLE_NAME
+        ))?;
+        let sources = stmt
+            .query_map(params![knowledge_base_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(sources)
+    }
+
+    /// Get the content hash a document was last indexed with.
+    pub fn get_document_hash(&mut self, knowledge_base_id: &str, path: &str) -> Result<Option<String>, KnowledgeError> {
+        let conn = self.pool.get()?;
+        let hash = conn
+            .query_row(
+                &format!(
+                    "SELECT content_hash FROM {} WHERE knowledge_base_id = ?1 AND path = ?2",
+                    Self::DOCUMENTS_TABLE_NAME
+                ),
+                params![knowledge_base_id, path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(hash)
+    }
+
+    pub fn list_document_paths(&mut self, knowledge_base_id: &str) -> Result<Vec<String>, KnowledgeError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT path FROM {} WHERE knowledge_base_id = ?1",
+            Self::DOCUMENTS_TABLE_NAME
+        ))?;
+        let paths = stmt
+            .query_map(params![knowledge_base_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(paths)
+    }
+
+    /// Replace a document and all of its chunks in one transaction.
+    pub fn replace_document(
+        &mut self,
+        knowledge_base_id: &str,
+        path: &str,
+        content_hash: &str,
+        chunks: &[(Chunk, Vec<f32>)],
+    ) -> Result<(), KnowledgeError> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE knowledge_base_id = ?1 AND path = ?2",
+                Self::DOCUMENTS_TABLE_NAME
+            ),
+            params![knowledge_base_id, path],
+        )?;
+        tx.execute(
+            &format!(
+                "INSERT INTO {} (knowledge_base_id, path, content_hash) VALUES (?1, ?2, ?3)",
+                Self::DOCUMENTS_TABLE_NAME
+            ),
+            params![knowledge_base_id, path, content_hash],
+        )?;
+        let document_id = tx.last_insert_rowid();
+        {
+            let mut stmt = tx.prepare(&format!(
+                "INSERT INTO {} (document_id, chunk_index, start_line, end_line, text, embedding) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                Self::CHUNKS_TABLE_NAME
+            ))?;
+            for (i, (chunk, embedding)) in chunks.iter().enumerate() {
+                stmt.execute(params![
+                    document_id,
+                    i as i64,
+                    chunk.start_line as i64,
+                    chunk.end_line as i64,
+                    chunk.text,
+                    embedding_to_bytes(embedding)
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn delete_document(&mut self, knowledge_base_id: &str, path: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE knowledge_base_id = ?1 AND path = ?2",
+                Self::DOCUMENTS_TABLE_NAME
+            ),
+            params![knowledge_base_id, path],
+        )?;
+        Ok(())
+    }
+
+    /// Get every chunk of a knowledge base with its embedding.
+    pub fn get_chunks(&mut self, knowledge_base_id: &str) -> Result<Vec<StoredChunk>, KnowledgeError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT d.path, c.start_line, c.end_line, c.text, c.embedding
+				FROM {} c JOIN {} d ON c.document_id = d.id
+				WHERE d.knowledge_base_id = ?1",
+            Self::CHUNKS_TABLE_NAME,
+            Self::DOCUMENTS_TABLE_NAME
+        ))?;
+        let chunks = stmt
+            .query_map(params![knowledge_base_id], |row| {
+                let embedding: Vec<u8> = row.get(4)?;
+                Ok(StoredChunk {
+                    path: row.get(0)?,
+                    chunk: Chunk {
+                        start_line: row.get::<_, i64>(1)? as usize,
+                        end_line: row.get::<_, i64>(2)? as usize,
+                        text: row.get(3)?,
+                    },
+                    embedding: bytes_to_embedding(&embedding),
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(chunks)
+    }
+
+    pub fn attach(&mut self, conversation_id: &str, knowledge_base_id: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO {} (conversation_id, knowledge_base_id) VALUES (?1, ?2)",
+                Self::ATTACHMENTS_TABLE_NAME
+            ),
+            params![conversation_id, knowledge_base_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn detach(&mut self, conversation_id: &str, knowledge_base_id: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE conversation_id = ?1 AND knowledge_base_id = ?2",
+                Self::ATTACHMENTS_TABLE_NAME
+            ),
+            params![conversation_id, knowledge_base_id],
+        )?;
+        Ok(())
+    }
+
+    /// Detach every knowledge base from a conversation.
+    pub fn detach_conversation(&mut self, conversation_id: &str) -> Result<(), KnowledgeError> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            &format!(
+                "DELETE FROM {} WHERE conversation_id = ?1",
+                Self::ATTACHMENTS_TABLE_NAME
+            ),
+            params![conversation_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_attached(&mut self, conversation_id: &str) -> Result<Vec<KnowledgeBaseInfo>, KnowledgeError> {
+        let ids = {
+            let conn = self.pool.get()?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT knowledge_base_id FROM {} WHERE conversation_id = ?1",
+                Self::ATTACHMENTS_TABLE_NAME
+            ))?;
+            let ids = stmt
+                .query_map(params![conversation_id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+            ids
+        };
+
+        let mut bases = Vec::new();
+        for id in ids {
+            if let Some(base) = self.get_base(&id)? {
+                bases.push(base);
+            }
+        }
+        Ok(bases)
+    }
+}
+
+fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
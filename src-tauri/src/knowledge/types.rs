@@ -0,0 +1,76 @@
+use thiserror::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Error)]
+pub enum KnowledgeError {
+    #[error("Database error in KnowledgeBase: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("IO error in KnowledgeBase: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Knowledge base not found: {0}")]
+    NotFound(String),
+    #[error("Embedding error: {0}")]
+    Embedding(String),
+    #[error("Unsupported document: {0}")]
+    UnsupportedDocument(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBaseInfo {
+    pub id: String,
+    pub name: String,
+    pub embedding_provider: String,
+    pub embedding_model: String,
+    pub sources: Vec<String>,
+}
+
+/// A piece of a document ready to be embedded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub text: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// A stored chunk together with the document it came from.
+#[derive(Debug, Clone)]
+pub struct StoredChunk {
+    pub path: String,
+    pub chunk: Chunk,
+    pub embedding: Vec<f32>,
+}
+
+/// Where a retrieved chunk came from, numbered the way it is cited in the prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub index: usize,
+    pub knowledge_base_id: String,
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetrievedChunk {
+    pub citation: Citation,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexReport {
+    pub indexed: usize,
+    pub skipped: usize,
+    pub removed: usize,
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexProgress {
+    pub knowledge_base_id: String,
+    pub path: String,
+    pub done: usize,
+    pub total: usize,
+}
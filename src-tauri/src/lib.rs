@@ -1,4 +1,5 @@
 mod api;
+mod audio;
 mod cache;
 mod commands;
 mod configs;
@@ -6,11 +7,16 @@ mod db;
 mod utils;
 mod inet;
 mod key_manager;
+mod knowledge;
+mod retry;
+mod scheduler;
 use tauri::{Builder, Manager};
 use db::chat::Chat;
 use cache::DiagramCache;
 use key_manager::KeyManager;
 use configs::ConfigManager;
+use knowledge::KnowledgeBase;
+use scheduler::Scheduler;
 use std::sync::Mutex;
 mod types;
 use types::AppData;
@@ -20,6 +26,8 @@ use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     Builder::default()
 		.setup(|app| {
 			let window = app.get_webview_window("main").unwrap();
@@ -36,13 +44,22 @@ pub fn run() {
 				diagram_cache: DiagramCache::new()?,
 				key_manager: KeyManager::new("wisp".to_string()),
 				config_manager,
+				knowledge: KnowledgeBase::new(app.handle())?,
 			}));
+			app.manage(Scheduler::new());
 			Ok(())
 		})
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             commands::ask_openai_stream,
+            commands::regenerate,
+            commands::fan_out,
+            commands::continue_message,
+            commands::complete_fim,
+            commands::generate_image,
+            commands::transcribe_audio,
+            commands::synthesize_speech,
             // commands::get_cached_render,
             commands::hash_content,
             commands::put_cached_diagram,
@@ -51,14 +68,41 @@ pub fn run() {
             commands::create_conversation,
             commands::add_message,
 			commands::get_message,
+			commands::get_message_attachments,
+			commands::get_message_generation,
 			commands::update_message,
 			commands::delete_message,
             commands::get_all_message_involved,
 			commands::get_thread_tree,
+			commands::get_branch_path,
+			commands::set_active_leaf,
+			commands::move_subtree,
+			commands::fork_conversation,
+			commands::check_thread_integrity,
             commands::delete_conversation,
             commands::list_conversations,
 			commands::update_conversation_entry_id,
 			commands::update_conversation,
+			commands::move_conversation_to_folder,
+			commands::create_folder,
+			commands::rename_folder,
+			commands::move_folder,
+			commands::delete_folder,
+			commands::list_folders,
+			commands::list_tags,
+			commands::delete_tag,
+			commands::add_conversation_tag,
+			commands::remove_conversation_tag,
+			commands::get_conversation_tags,
+			commands::add_message_tag,
+			commands::remove_message_tag,
+			commands::get_message_tags,
+			commands::list_tagged_messages,
+			commands::get_conversation_fallbacks,
+			commands::set_conversation_fallbacks,
+			commands::generate_title,
+			commands::compact_conversation,
+			commands::delete_compaction,
 			commands::get_url,
 			commands::post_url,
 			commands::set_api_key,
@@ -73,6 +117,17 @@ pub fn run() {
 			commands::configs_get_model,
 			commands::configs_update_model,
 			commands::configs_delete_model,
+			commands::configs_get_title_generation,
+			commands::configs_set_title_generation,
+			commands::knowledge_create_base,
+			commands::knowledge_list_bases,
+			commands::knowledge_delete_base,
+			commands::knowledge_add_source,
+			commands::knowledge_remove_source,
+			commands::knowledge_index,
+			commands::knowledge_attach,
+			commands::knowledge_detach,
+			commands::knowledge_list_attached,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
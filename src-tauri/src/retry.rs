@@ -0,0 +1,224 @@
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use thiserror::Error;
+
+use crate::configs::provider::{Provider, RetryPolicy};
+
+#[derive(Debug, Error)]
+pub enum ProviderCallError {
+    #[error("{path} returned {status}: {body}")]
+    Status {
+        path: String,
+        status: StatusCode,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("{0}")]
+    Client(String),
+    #[error("Connection error: {0}")]
+    Connection(#[from] reqwest::Error),
+    #[error("Stream error: {0}")]
+    Stream(String),
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl ProviderCallError {
+    /// Whether the same request may succeed when sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ProviderCallError::Status { status, .. } => matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504),
+            ProviderCallError::Connection(e) => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+            ProviderCallError::Stream(_) => true,
+            ProviderCallError::Client(_) | ProviderCallError::InvalidResponse(_) => false,
+        }
+    }
+
+    /// Whether another provider or model may succeed where this one failed
+    pub fn allows_fallback(&self) -> bool {
+        match self {
+            ProviderCallError::Status { status, .. } => {
+                self.is_transient() || matches!(status.as_u16(), 401 | 403 | 404)
+            }
+            ProviderCallError::Client(_) => true,
+            _ => self.is_transient(),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderCallError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Reported to the frontend as `provider_retry` before every retry
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryNotice {
+    pub provider: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+/// Reads how long the server asked us to wait, from `retry-after-ms` or
+/// `Retry-After` in seconds or as an HTTP date. Values that are not finite are
+/// ignored; values too large to be a duration saturate and are capped by the
+/// policy.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    retry_after_at(headers, Utc::now())
+}
+
+fn retry_after_at(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    let number = |name: &str| {
+        header(name)
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+    };
+
+    if let Some(ms) = number("retry-after-ms") {
+        return Some(Duration::try_from_secs_f64(ms.max(0.0) / 1000.0).unwrap_or(Duration::MAX));
+    }
+    if let Some(secs) = number("retry-after") {
+        return Some(Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX));
+    }
+    // A date in the past means the request may be retried right away
+    let date = DateTime::parse_from_rfc2822(header("retry-after")?).ok()?;
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Exponential backoff with jitter for the retry after `attempt` failed
+/// attempts. A delay requested by the server takes precedence, up to the
+/// policy's maximum delay.
+fn backoff_delay(policy: &RetryPolicy, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(retry_after) = retry_after {
+        return retry_after.min(Duration::from_millis(policy.max_delay_ms));
+    }
+    let exponential = policy
+        .initial_delay_ms
+        .saturating_mul(1u64 << (attempt - 1).min(32))
+        .min(policy.max_delay_ms);
+    // Wait between half and all of the delay so clients do not retry in lockstep
+    let jittered = exponential / 2 + (fastrand::f64() * (exponential / 2) as f64) as u64;
+    Duration::from_millis(jittered)
+}
+
+/// Runs `call` until it succeeds, fails with a permanent error, or the
+/// provider's retry policy runs out of attempts
+pub async fn with_retry<T, F, Fut>(
+    app_handle: &AppHandle,
+    provider: &Provider,
+    mut call: F,
+) -> Result<T, ProviderCallError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ProviderCallError>>,
+{
+    let policy = &provider.retry;
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Ok(value) => return Ok(value),
+            Err(e) if e.is_transient() && attempt < policy.max_attempts => {
+                let delay = backoff_delay(policy, attempt, e.retry_after());
+                let _ = app_handle.emit(
+                    "provider_retry",
+                    RetryNotice {
+                        provider: provider.name.clone(),
+                        attempt,
+                        max_attempts: policy.max_attempts,
+                        delay_ms: delay.as_millis() as u64,
+                        reason: e.to_string(),
+                    },
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            initial_delay_ms: 500,
+            max_delay_ms: 4_000,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = policy();
+        for (attempt, full_ms) in [(1, 500), (2, 1_000), (3, 2_000), (4, 4_000), (5, 4_000), (40, 4_000)] {
+            for _ in 0..50 {
+                let delay = backoff_delay(&policy, attempt, None).as_millis() as u64;
+                assert!(
+                    (full_ms / 2..=full_ms).contains(&delay),
+                    "attempt {attempt}: {delay} ms outside {}..={full_ms}",
+                    full_ms / 2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_overrides_backoff_up_to_the_cap() {
+        let policy = policy();
+        assert_eq!(backoff_delay(&policy, 1, Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(backoff_delay(&policy, 5, Some(Duration::ZERO)), Duration::ZERO);
+        assert_eq!(backoff_delay(&policy, 1, Some(Duration::from_secs(60))), Duration::from_millis(4_000));
+        assert_eq!(backoff_delay(&policy, 1, Some(Duration::MAX)), Duration::from_millis(4_000));
+    }
+
+    #[test]
+    fn retry_after_in_seconds_and_milliseconds() {
+        let now = Utc::now();
+        assert_eq!(retry_after_at(&headers(&[("retry-after", "7")]), now), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after_at(&headers(&[("retry-after", " 1.5 ")]), now), Some(Duration::from_millis(1_500)));
+        assert_eq!(retry_after_at(&headers(&[("retry-after", "-3")]), now), Some(Duration::ZERO));
+        assert_eq!(retry_after_at(&headers(&[("retry-after", "1e400")]), now), None);
+        assert_eq!(retry_after_at(&headers(&[("retry-after", "1e300")]), now), Some(Duration::MAX));
+        // The millisecond header is more precise and wins
+        assert_eq!(
+            retry_after_at(&headers(&[("retry-after", "7"), ("retry-after-ms", "250")]), now),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(retry_after_at(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT").unwrap().with_timezone(&Utc);
+        assert_eq!(
+            retry_after_at(&headers(&[("retry-after", "Wed, 21 Oct 2026 07:28:30 GMT")]), now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after_at(&headers(&[("retry-after", "Wed, 21 Oct 2026 07:27:00 GMT")]), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after_at(&headers(&[("retry-after", "soon")]), now), None);
+    }
+}
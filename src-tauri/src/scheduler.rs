@@ -0,0 +1,285 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::configs::provider::{Provider, ProviderLimits};
+
+/// Length of the window requests and tokens per minute are counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Which queued request is let through first when a provider is at its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Background,
+    Interactive,
+}
+
+#[derive(Default)]
+struct ProviderState {
+    in_flight: u32,
+    /// Start times of the requests in the current window
+    requests: VecDeque<Instant>,
+    /// Start times and estimated tokens of the requests in the current window
+    tokens: VecDeque<(Instant, u32)>,
+    /// Queued tickets as (priority, sequence number)
+    queue: Vec<(Priority, u64)>,
+}
+
+impl ProviderState {
+    fn prune(&mut self, now: Instant) {
+        while self.requests.front().is_some_and(|t| now.duration_since(*t) >= WINDOW) {
+            self.requests.pop_front();
+        }
+        while self.tokens.front().is_some_and(|(t, _)| now.duration_since(*t) >= WINDOW) {
+            self.tokens.pop_front();
+        }
+    }
+
+    /// The ticket that goes next: highest priority, then first come
+    fn next_ticket(&self) -> Option<(Priority, u64)> {
+        self.queue.iter().copied().max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+    }
+
+    /// How long until a request of `tokens` fits the limits, or `None` if it
+    /// fits now. `Duration::MAX` means it has to wait for a running request.
+    fn wait_time(&self, limits: &ProviderLimits, tokens: u32, now: Instant) -> Option<Duration> {
+        if limits.max_concurrent.is_some_and(|max| max > 0 && self.in_flight >= max) {
+            return Some(Duration::MAX);
+        }
+        let until_expired = |start: Instant| WINDOW.saturating_sub(now.duration_since(start));
+
+        if let Some(rpm) = limits.requests_per_minute {
+            if self.requests.len() as u32 >= rpm {
+                return self.requests.front().map(|t| until_expired(*t));
+            }
+        }
+        if let Some(tpm) = limits.tokens_per_minute {
+            let mut used: u32 = self.tokens.iter().map(|(_, n)| n).sum();
+            if used.saturating_add(tokens) > tpm {
+                // Wait for enough earlier requests to leave the window. A request
+                // larger than the whole budget goes through once the window is empty.
+                for (start, n) in &self.tokens {
+                    used -= n;
+                    if used == 0 || used.saturating_add(tokens) <= tpm {
+                        return Some(until_expired(*start));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+struct Inner {
+    providers: Mutex<HashMap<String, ProviderState>>,
+    next_sequence: Mutex<u64>,
+    notify: Notify,
+}
+
+/// Queues requests to providers so they stay within the concurrency, request
+/// and token limits configured on each provider.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+/// Held for the duration of a request; frees its concurrency slot on drop.
+pub struct Permit {
+    inner: Arc<Inner>,
+    provider: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut providers = self.inner.providers.lock().unwrap();
+        if let Some(state) = providers.get_mut(&self.provider) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+        drop(providers);
+        self.inner.notify.notify_waiters();
+    }
+}
+
+/// Removes a ticket from the queue when its request is cancelled while waiting
+struct Ticket<'a> {
+    inner: &'a Inner,
+    provider: &'a str,
+    key: (Priority, u64),
+    queued: bool,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if self.queued {
+            let mut providers = self.inner.providers.lock().unwrap();
+            if let Some(state) = providers.get_mut(self.provider) {
+                state.queue.retain(|k| *k != self.key);
+            }
+            drop(providers);
+            self.inner.notify.notify_waiters();
+        }
+    }
+}
+
+#[allow(unused)]
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                providers: Mutex::new(HashMap::new()),
+                next_sequence: Mutex::new(0),
+                notify: Notify::new(),
+            }),
+        }
+    }
+
+    /// Wait until a request of about `tokens` tokens may be sent to `provider`
+    pub async fn acquire(&self, provider: &Provider, priority: Priority, tokens: u32) -> Permit {
+        let limits = &provider.limits;
+        let sequence = {
+            let mut next = self.inner.next_sequence.lock().unwrap();
+            *next += 1;
+            *next
+        };
+        let mut ticket = Ticket {
+            inner: &self.inner,
+            provider: &provider.name,
+            key: (priority, sequence),
+            queued: true,
+        };
+        self.inner
+            .providers
+            .lock()
+            .unwrap()
+            .entry(provider.name.clone())
+            .or_default()
+            .queue
+            .push(ticket.key);
+
+        loop {
+            // Register for wake-ups before checking, so a release in between is not missed
+            let notified = self.inner.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let wait = {
+                let mut providers = self.inner.providers.lock().unwrap();
+                let state = providers.entry(provider.name.clone()).or_default();
+                let now = Instant::now();
+                state.prune(now);
+
+                if state.next_ticket() != Some(ticket.key) {
+                    Some(Duration::MAX)
+                } else {
+                    match state.wait_time(limits, tokens, now) {
+                        Some(wait) => Some(wait),
+                        None => {
+                            state.queue.retain(|k| *k != ticket.key);
+                            state.in_flight += 1;
+                            state.requests.push_back(now);
+                            state.tokens.push_back((now, tokens));
+                            None
+                        }
+                    }
+                }
+            };
+
+            match wait {
+                None => {
+                    ticket.queued = false;
+                    // The next ticket in the queue may fit as well
+                    self.inner.notify.notify_waiters();
+                    return Permit {
+                        inner: self.inner.clone(),
+                        provider: provider.name.clone(),
+                    };
+                }
+                Some(Duration::MAX) => notified.await,
+                Some(wait) => {
+                    let _ = tokio::time::timeout(wait, notified).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_concurrent: Option<u32>, rpm: Option<u32>, tpm: Option<u32>) -> ProviderLimits {
+        ProviderLimits {
+            max_concurrent,
+            requests_per_minute: rpm,
+            tokens_per_minute: tpm,
+        }
+    }
+
+    /// State at `now` after requests that started the given times ago
+    fn state_with(now: Instant, started: &[(u64, u32)]) -> ProviderState {
+        let mut state = ProviderState::default();
+        for &(secs_ago, tokens) in started {
+            let start = now - Duration::from_secs(secs_ago);
+            state.requests.push_back(start);
+            state.tokens.push_back((start, tokens));
+        }
+        state.prune(now);
+        state
+    }
+
+    #[test]
+    fn requests_wait_for_the_oldest_to_leave_the_window() {
+        let now = Instant::now();
+        let limits = limits(None, Some(2), None);
+        let state = state_with(now, &[(45, 0), (10, 0)]);
+        assert_eq!(state.wait_time(&limits, 0, now), Some(Duration::from_secs(15)));
+        // One second before the edge the request still waits, at the edge it is gone
+        let state = state_with(now, &[(59, 0), (10, 0)]);
+        assert_eq!(state.wait_time(&limits, 0, now), Some(Duration::from_secs(1)));
+        let state = state_with(now, &[(60, 0), (10, 0)]);
+        assert_eq!(state.requests.len(), 1);
+        assert_eq!(state.wait_time(&limits, 0, now), None);
+    }
+
+    #[test]
+    fn tokens_wait_until_enough_have_left_the_window() {
+        let now = Instant::now();
+        let limits = limits(None, None, Some(1_000));
+        let state = state_with(now, &[(50, 400), (30, 300), (20, 200)]);
+        assert_eq!(state.wait_time(&limits, 100, now), None);
+        // 900 used: freeing the first 400 makes room for 400
+        assert_eq!(state.wait_time(&limits, 400, now), Some(Duration::from_secs(10)));
+        // 500 do not fit until the second request has left too
+        assert_eq!(state.wait_time(&limits, 500, now), Some(Duration::from_secs(10)));
+        assert_eq!(state.wait_time(&limits, 501, now), Some(Duration::from_secs(30)));
+        // A request over the whole budget waits for the window to empty
+        assert_eq!(state.wait_time(&limits, 5_000, now), Some(Duration::from_secs(40)));
+    }
+
+    #[test]
+    fn concurrency_limit_waits_for_a_running_request() {
+        let now = Instant::now();
+        let mut state = ProviderState {
+            in_flight: 2,
+            ..Default::default()
+        };
+        assert_eq!(state.wait_time(&limits(Some(2), None, None), 0, now), Some(Duration::MAX));
+        // Zero means unlimited
+        assert_eq!(state.wait_time(&limits(Some(0), None, None), 0, now), None);
+        state.in_flight = 1;
+        assert_eq!(state.wait_time(&limits(Some(2), None, None), 0, now), None);
+    }
+
+    #[test]
+    fn queued_tickets_go_by_priority_then_arrival() {
+        let mut state = ProviderState::default();
+        assert_eq!(state.next_ticket(), None);
+        state.queue = vec![(Priority::Background, 1), (Priority::Interactive, 3), (Priority::Interactive, 2)];
+        assert_eq!(state.next_ticket(), Some((Priority::Interactive, 2)));
+        state.queue.retain(|t| t.0 == Priority::Background);
+        assert_eq!(state.next_ticket(), Some((Priority::Background, 1)));
+    }
+}
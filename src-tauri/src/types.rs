@@ -1,12 +1,51 @@
 use crate::db;
+use serde::{Deserialize, Serialize};
+use super::configs::provider::Provider;
 use db::chat::Chat;
 use super::cache::DiagramCache;
 use super::key_manager::KeyManager;
 use super::configs::ConfigManager;
+use super::knowledge::KnowledgeBase;
 
 pub struct AppData {
 	pub chat: Chat,
 	pub diagram_cache: DiagramCache,
 	pub key_manager: KeyManager,
 	pub config_manager: ConfigManager,
+	pub knowledge: KnowledgeBase,
+}
+
+/// One (provider, model) pair to send a prompt to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelTarget {
+	pub provider: Provider,
+	pub model: String,
+}
+
+/// The outcome of one model's reply when several stream at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanOutResult {
+	pub message_id: String,
+	pub provider: String,
+	pub model: String,
+	pub error: Option<String>,
+}
+
+/// Reported to the frontend as `provider_fallback` when a request moves on to
+/// the next entry of its fallback chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackNotice {
+	pub from_provider: String,
+	pub from_model: String,
+	pub to_provider: String,
+	pub to_model: String,
+	pub reason: String,
+}
+
+/// Reported to the frontend as `conversation_title` when a conversation is
+/// given a generated title.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTitle {
+	pub conversation_id: String,
+	pub name: String,
 }
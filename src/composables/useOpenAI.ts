@@ -4,7 +4,7 @@ import { ref } from 'vue'
 import { INTERFACE_PROMPT, INTERFACE_REGENERATE_INSERT } from '../prompt-management/constants/interfacePrompt'
 import { cloneDeep } from 'lodash'
 import { getUrl } from '../libs/commands'
-import type { Model, Provider } from '../libs/types'
+import type { Model, Provider, StreamChunk } from '../libs/types'
 
 export function useOpenAI() {
 	const isStreaming = ref(false)
@@ -13,19 +13,22 @@ export function useOpenAI() {
 		messages: any[],
 		model: string,
 		provider: Provider,
-		onContentChunk: (chunk: string) => void,
-		onReasoningChunk: (chunk: string) => void,
+		onContentChunk: (chunk: string, index: number) => void,
+		onReasoningChunk: (chunk: string, index: number) => void,
 		onFinish: () => void,
 		ignoreLastMessage: boolean = false,
 		insertRegenerateGuidancePrompt: boolean = false,
-	): Promise<void> => {
+		conversationId?: string,
+		parentId?: string,
+		n?: number,
+	): Promise<string[]> => {
 		isStreaming.value = true
-		const unlistenContent = await listen<string>('openai_stream_chunk', (event) => {
-			onContentChunk(event.payload)
+		const unlistenContent = await listen<StreamChunk>('openai_stream_chunk', (event) => {
+			onContentChunk(event.payload.delta, event.payload.index)
 		})
 
-		const unlistenReasoning = await listen<string>('openai_stream_chunk_reasoning', (event) => {
-			onReasoningChunk(event.payload)
+		const unlistenReasoning = await listen<StreamChunk>('openai_stream_chunk_reasoning', (event) => {
+			onReasoningChunk(event.payload.delta, event.payload.index)
 		})
 
 		try {
@@ -34,12 +37,16 @@ export function useOpenAI() {
 			if (ignoreLastMessage) messages = messages.slice(0, -1)
 			if (insertRegenerateGuidancePrompt) messages.push({role: "system", content: INTERFACE_REGENERATE_INSERT})
 
-			if (messages.length > 0) await invoke('ask_openai_stream', {
+			if (messages.length > 0) return await invoke<string[]>('ask_openai_stream', {
 				messages,
 				model,
-				provider
+				provider,
+				conversationId,
+				parentId,
+				n
 			})
-			else console.warn('[useOpenAI] No messages to stream')
+			console.warn('[useOpenAI] No messages to stream')
+			return []
 		}
 		catch (error) {
 			console.error('[useOpenAI] Error streaming response:', error)
@@ -53,6 +60,33 @@ export function useOpenAI() {
 		}
 	}
 
+	const streamFim = async (
+		prefix: string,
+		suffix: string,
+		model: string,
+		provider: Provider,
+		onContentChunk: (chunk: string) => void,
+		onFinish?: () => void,
+	): Promise<void> => {
+		isStreaming.value = true
+		const unlistenContent = await listen<StreamChunk>('openai_stream_chunk', (event) => {
+			onContentChunk(event.payload.delta)
+		})
+
+		try {
+			await invoke('complete_fim', { prefix, suffix, model, provider })
+		}
+		catch (error) {
+			console.error('[useOpenAI] Error streaming completion:', error)
+			return Promise.reject("Fail to stream completion: " + error)
+		}
+		finally {
+			unlistenContent()
+			isStreaming.value = false
+			if (onFinish) onFinish()
+		}
+	}
+
 	const fetchModels = async (baseUrl: string, apiKey: string): Promise<Model[]> => {
 		try {
 			const response = await getUrl({
@@ -98,6 +132,7 @@ export function useOpenAI() {
 	return {
 		isStreaming,
 		streamResponse,
+		streamFim,
 		fetchModels
 	}
 }
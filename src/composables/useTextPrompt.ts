@@ -0,0 +1,29 @@
+import { h, ref } from 'vue'
+import { NInput, useDialog } from 'naive-ui'
+
+// Asks for a line of text in a dialog. Resolves to null when cancelled or left empty.
+export function useTextPrompt() {
+	const dialog = useDialog()
+
+	const prompt = (title: string, placeholder: string, initial = '') => {
+		return new Promise<string | null>((resolve) => {
+			const text = ref(initial)
+			dialog.create({
+				title,
+				content: () =>
+					h(NInput, {
+						value: text.value,
+						placeholder,
+						onUpdateValue: (v: string) => (text.value = v),
+					}),
+				positiveText: 'OK',
+				negativeText: 'Cancel',
+				onPositiveClick: () => resolve(text.value.trim() || null),
+				onNegativeClick: () => resolve(null),
+				onClose: () => resolve(null),
+			})
+		})
+	}
+
+	return { prompt }
+}
@@ -1,5 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
-import { Message, Conversation, Provider, Model } from "./types";
+import { Message, Conversation, ConversationQuery, Folder, Tag, TaggedMessage, Provider, Model, Attachment, Generation, Compaction, IntegrityReport, ModelTarget, FanOutResult, FallbackTarget, TitleGeneration, KnowledgeBaseInfo, KnowledgeIndexReport } from "./types";
 
 export async function hashContent(content: string) {
 	return invoke<string>('hash_content', { content })
@@ -21,6 +21,46 @@ export async function getMessage(messageId: string) {
 	return invoke<Message>('get_message', { messageId })
 }
 
+export async function getMessageAttachments(messageId: string) {
+	return invoke<Attachment[]>('get_message_attachments', { messageId })
+}
+
+export async function getMessageGeneration(messageId: string) {
+	return invoke<Generation | null>('get_message_generation', { messageId })
+}
+
+export async function generateImage(conversationId: string, prompt: string, model: string, provider: Provider, parentId?: string) {
+	return invoke<string>('generate_image', { conversationId, parentId, prompt, model, provider })
+}
+
+export async function transcribeAudio(conversationId: string, path: string, model: string, provider: Provider, parentId?: string) {
+	return invoke<string>('transcribe_audio', { conversationId, parentId, path, model, provider })
+}
+
+export async function synthesizeSpeech(messageId: string, model: string, provider: Provider) {
+	return invoke<string>('synthesize_speech', { messageId, model, provider })
+}
+
+export async function regenerate(conversationId: string, messageId: string, model: string, provider: Provider, systemPrompt?: string) {
+	return invoke<string>('regenerate', { conversationId, messageId, model, provider, systemPrompt })
+}
+
+export async function continueMessage(messageId: string, model: string, provider: Provider, systemPrompt?: string) {
+	return invoke<void>('continue_message', { messageId, model, provider, systemPrompt })
+}
+
+export async function fanOut(conversationId: string, parentId: string, targets: ModelTarget[], systemPrompt?: string) {
+	return invoke<FanOutResult[]>('fan_out', { conversationId, parentId, targets, systemPrompt })
+}
+
+export async function compactConversation(messageId: string, model: string, provider: Provider) {
+	return invoke<Compaction>('compact_conversation', { messageId, model, provider })
+}
+
+export async function deleteCompaction(messageId: string) {
+	return invoke<void>('delete_compaction', { messageId })
+}
+
 export async function deleteMessage(messageId: string, recursive: boolean) {
 	return invoke<string | null>('delete_message', { messageId, recursive })
 }
@@ -29,6 +69,26 @@ export async function getAllMessageInvolved(conversationId: string) {
 	return invoke<Message[]>('get_all_message_involved', { conversationId })
 }
 
+export async function getBranchPath(leafMessageId: string) {
+	return invoke<Message[]>('get_branch_path', { leafMessageId })
+}
+
+export async function setActiveLeaf(conversationId: string, messageId: string) {
+	return invoke<void>('set_active_leaf', { conversationId, messageId })
+}
+
+export async function moveSubtree(messageId: string, newParentId?: string) {
+	return invoke<void>('move_subtree', { messageId, newParentId })
+}
+
+export async function forkConversation(messageId: string, name: string, includeSubtree?: boolean) {
+	return invoke<string>('fork_conversation', { messageId, name, includeSubtree })
+}
+
+export async function checkThreadIntegrity(repair: boolean) {
+	return invoke<IntegrityReport>('check_thread_integrity', { repair })
+}
+
 type GetThreadTreeResponse = {
 	key: string,
 	parent: string | null,
@@ -50,8 +110,80 @@ export async function deleteConversation(conversationId: string) {
 	return invoke<void>('delete_conversation', { conversationId })
 }
 
-export async function listConversations() {
-	return invoke<Conversation[]>('list_conversations', {})
+export async function listConversations(query?: ConversationQuery) {
+	return invoke<Conversation[]>('list_conversations', { query })
+}
+
+export async function moveConversationToFolder(conversationId: string, folderId?: string) {
+	return invoke<void>('move_conversation_to_folder', { conversationId, folderId })
+}
+
+export async function createFolder(name: string, parentId?: string) {
+	return invoke<string>('create_folder', { name, parentId })
+}
+
+export async function renameFolder(folderId: string, name: string) {
+	return invoke<void>('rename_folder', { folderId, name })
+}
+
+export async function moveFolder(folderId: string, parentId?: string) {
+	return invoke<void>('move_folder', { folderId, parentId })
+}
+
+export async function deleteFolder(folderId: string) {
+	return invoke<void>('delete_folder', { folderId })
+}
+
+export async function listFolders() {
+	return invoke<Folder[]>('list_folders', {})
+}
+
+export async function listTags() {
+	return invoke<Tag[]>('list_tags', {})
+}
+
+export async function deleteTag(tagId: string) {
+	return invoke<void>('delete_tag', { tagId })
+}
+
+export async function addConversationTag(conversationId: string, name: string) {
+	return invoke<Tag>('add_conversation_tag', { conversationId, name })
+}
+
+export async function removeConversationTag(conversationId: string, tagId: string) {
+	return invoke<void>('remove_conversation_tag', { conversationId, tagId })
+}
+
+export async function getConversationTags(conversationId: string) {
+	return invoke<Tag[]>('get_conversation_tags', { conversationId })
+}
+
+export async function addMessageTag(messageId: string, name: string) {
+	return invoke<Tag>('add_message_tag', { messageId, name })
+}
+
+export async function removeMessageTag(messageId: string, tagId: string) {
+	return invoke<void>('remove_message_tag', { messageId, tagId })
+}
+
+export async function getMessageTags(messageId: string) {
+	return invoke<Tag[]>('get_message_tags', { messageId })
+}
+
+export async function listTaggedMessages(tagId?: string) {
+	return invoke<TaggedMessage[]>('list_tagged_messages', { tagId })
+}
+
+export async function generateTitle(conversationId: string) {
+	return invoke<string | null>('generate_title', { conversationId })
+}
+
+export async function getConversationFallbacks(conversationId: string) {
+	return invoke<FallbackTarget[]>('get_conversation_fallbacks', { conversationId })
+}
+
+export async function setConversationFallbacks(conversationId: string, fallbacks: FallbackTarget[]) {
+	return invoke<void>('set_conversation_fallbacks', { conversationId, fallbacks })
 }
 
 export interface DiagramCacheEntry {
@@ -147,3 +279,48 @@ export async function configsUpdateModel(providerName: string, modelName: string
 export async function configsDeleteModel(providerName: string, modelName: string) {
     return invoke<void>('configs_delete_model', { providerName, modelName })
 }
+
+export async function configsGetTitleGeneration() {
+    return invoke<TitleGeneration>('configs_get_title_generation', {})
+}
+
+export async function configsSetTitleGeneration(titleGeneration: TitleGeneration) {
+    return invoke<void>('configs_set_title_generation', { titleGeneration })
+}
+
+// Knowledge base commands
+export async function knowledgeCreateBase(name: string, embeddingProvider: string, embeddingModel: string) {
+    return invoke<string>('knowledge_create_base', { name, embeddingProvider, embeddingModel })
+}
+
+export async function knowledgeListBases() {
+    return invoke<KnowledgeBaseInfo[]>('knowledge_list_bases', {})
+}
+
+export async function knowledgeDeleteBase(knowledgeBaseId: string) {
+    return invoke<void>('knowledge_delete_base', { knowledgeBaseId })
+}
+
+export async function knowledgeAddSource(knowledgeBaseId: string, path: string) {
+    return invoke<void>('knowledge_add_source', { knowledgeBaseId, path })
+}
+
+export async function knowledgeRemoveSource(knowledgeBaseId: string, path: string) {
+    return invoke<void>('knowledge_remove_source', { knowledgeBaseId, path })
+}
+
+export async function knowledgeIndex(knowledgeBaseId: string) {
+    return invoke<KnowledgeIndexReport>('knowledge_index', { knowledgeBaseId })
+}
+
+export async function knowledgeAttach(conversationId: string, knowledgeBaseId: string) {
+    return invoke<void>('knowledge_attach', { conversationId, knowledgeBaseId })
+}
+
+export async function knowledgeDetach(conversationId: string, knowledgeBaseId: string) {
+    return invoke<void>('knowledge_detach', { conversationId, knowledgeBaseId })
+}
+
+export async function knowledgeListAttached(conversationId: string) {
+    return invoke<KnowledgeBaseInfo[]>('knowledge_list_attached', { conversationId })
+}
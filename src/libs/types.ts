@@ -12,6 +12,38 @@ export type Message = {
 	timestamp: number,
 	tokens?: number,
 	embedding?: Uint8Array,
+	compaction?: Compaction,
+}
+
+export type Compaction = {
+	message_id: string,
+	summary: string,
+	provider: string,
+	model: string,
+}
+
+export type Attachment = {
+	id: string,
+	message_id: string,
+	name: string,
+	mime_type: string,
+	data: string, // base64
+}
+
+export type IntegrityReport = {
+	multiple_parents: string[],
+	dangling_parents: string[],
+	without_conversation: string[],
+	repaired: boolean,
+	reattached: string[], // moved under their conversation's entry message, or made its entry
+	deleted: string[], // belonged to no conversation
+}
+
+export type Generation = {
+	message_id: string,
+	provider: string,
+	model: string,
+	parameters?: Record<string, any>,
 }
 
 export type Conversation = {
@@ -19,12 +51,59 @@ export type Conversation = {
 	name: string,
 	description?: string,
 	entry_message_id?: string,
+	active_leaf_id?: string,
+	forked_from_conversation_id?: string,
+	forked_from_message_id?: string,
+	created_at: number, // unix seconds
+	updated_at: number, // unix seconds
+	pinned: boolean,
+	archived: boolean,
+	folder_id?: string,
+}
+
+export type Folder = {
+	id: string,
+	name: string,
+	parent_id?: string,
+	created_at: number, // unix seconds
+}
+
+// "any" lists every conversation, "root" those outside any folder
+export type FolderFilter = "any" | "root" | { folder: string }
+
+export type ConversationQuery = {
+	sort?: "name" | "created_at" | "updated_at",
+	ascending?: boolean,
+	limit?: number,
+	offset?: number,
+	filter?: string,
+	folder?: FolderFilter,
+	archived?: boolean, // list archived conversations instead of the others
+	tags?: string[], // tag ids, all of which a conversation must carry
+}
+
+export type Tag = {
+	id: string,
+	name: string,
+}
+
+export type TaggedMessage = {
+	conversation_id: string,
+	conversation_name: string,
+	message: Message,
 }
 
 export enum TextModelCapability {
 	FIM = "FIM",
 	ToolUse = "ToolUse",
 	Reasoning = "Reasoning",
+	Prefill = "Prefill",
+}
+
+export enum ReasoningPolicy {
+	Drop = "Drop",
+	Keep = "Keep",
+	ThinkingBlocks = "ThinkingBlocks",
 }
 
 export interface ModelMetadata {
@@ -67,6 +146,13 @@ export interface RerankerParams {
 	score_threshold?: number;
 }
 
+export interface AudioParams {
+	voice?: string;
+	response_format?: string;
+	speed?: number;
+	language?: string;
+}
+
 export interface VisionSupport {
 	context_window?: number;
 	max_resolution?: [number, number];
@@ -89,11 +175,11 @@ export interface MultimodalConfig {
 }
 
 export type ModelInfo =
-	| { type: "text_generation", "configs": { parameters: TextGenerationParams, capabilities: TextModelCapability[], multimodal?: MultimodalConfig } }
+	| { type: "text_generation", "configs": { parameters: TextGenerationParams, capabilities: TextModelCapability[], multimodal?: MultimodalConfig, reasoning_policy?: ReasoningPolicy, parse_think_tags?: boolean } }
 	| { type: "image_generation", "configs": { parameters: ImageGenerationParams } }
 	| { type: "embedding", "configs": { parameters: EmbeddingParams } }
 	| { type: "reranker", "configs": { parameters: RerankerParams } }
-	| { type: "audio" }
+	| { type: "audio", configs?: { parameters?: AudioParams, support?: AudioSupport } }
 
 export interface Model {
 	metadata: ModelMetadata;
@@ -101,6 +187,31 @@ export interface Model {
 	tokenizer?: string;
 	max_input_size: number;
 	api_endpoint?: string;
+	fallbacks?: FallbackTarget[];
+}
+
+export interface TitleGeneration {
+	enabled: boolean;
+	provider: string;
+	model: string;
+}
+
+export interface ConversationTitle {
+	conversation_id: string;
+	name: string;
+}
+
+export interface FallbackTarget {
+	provider: string;
+	model: string;
+}
+
+export interface FallbackNotice {
+	from_provider: string;
+	from_model: string;
+	to_provider: string;
+	to_model: string;
+	reason: string;
 }
 
 export interface Provider {
@@ -108,4 +219,74 @@ export interface Provider {
 	display_name: string;
 	base_url: string;
 	models: Model[];
+	retry?: RetryPolicy;
+	limits?: ProviderLimits;
+}
+
+export interface ProviderLimits {
+	max_concurrent?: number;
+	requests_per_minute?: number;
+	tokens_per_minute?: number;
+}
+
+export interface RetryPolicy {
+	max_attempts: number;
+	initial_delay_ms: number;
+	max_delay_ms: number;
+}
+
+export interface RetryNotice {
+	provider: string;
+	attempt: number;
+	max_attempts: number;
+	delay_ms: number;
+	reason: string;
+}
+
+export interface KnowledgeBaseInfo {
+	id: string;
+	name: string;
+	embedding_provider: string;
+	embedding_model: string;
+	sources: string[];
+}
+
+export interface KnowledgeCitation {
+	index: number;
+	knowledge_base_id: string;
+	path: string;
+	start_line: number;
+	end_line: number;
+	score: number;
+}
+
+export interface KnowledgeIndexReport {
+	indexed: number;
+	skipped: number;
+	removed: number;
+	failed: string[];
+}
+
+export interface ModelTarget {
+	provider: Provider;
+	model: string;
+}
+
+export interface FanOutResult {
+	message_id: string;
+	provider: string;
+	model: string;
+	error?: string;
+}
+
+export interface StreamChunk {
+	index: number;
+	delta: string;
+}
+
+export interface TaggedStreamChunk {
+	message_id: string;
+	provider: string;
+	model: string;
+	delta: string;
 }
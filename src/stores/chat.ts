@@ -1,6 +1,7 @@
 import { defineStore } from 'pinia'
 import { ref, watch, type ComputedRef, computed, reactive } from 'vue'
-import type { Message, Conversation, Provider } from '../libs/types'
+import type { Message, Compaction, Conversation, ConversationQuery, ConversationTitle, Folder, Provider, Tag, TaggedMessage } from '../libs/types'
+import { listen } from '@tauri-apps/api/event'
 import * as Commands from '../libs/commands'
 import MessageThreadTree from '../libs/message-thread-tree'
 import { MessageRole } from '../libs/types';
@@ -15,12 +16,18 @@ type MessageDisplay = Omit<Omit<Message, 'text'>, 'reasoning'> & {
 	reasoning: ComputedRef<string>,
 }
 
+const CONVERSATION_PAGE_SIZE = 50
+
 export const useChatStore = defineStore('chat', () => {
 	const userInput = ref('')
 	const currentConversationId = ref<string | null>(null)
 	const threadTree = reactive<MessageThreadTree>(new MessageThreadTree())
 	const rootMessageId = ref<string | null>(null)
 	const conversations = ref<Conversation[]>([])
+	const conversationQuery = ref<ConversationQuery>({})
+	const hasMoreConversations = ref(true)
+	const folders = ref<Folder[]>([])
+	const tags = ref<Tag[]>([])
 	const chosenModel = ref<string | null>(null)
 	const chosenProvider = ref<Provider | null>(null)
 
@@ -36,7 +43,14 @@ export const useChatStore = defineStore('chat', () => {
 		onReceiving: (chunk: string, isReasoning: boolean) => void;
 		onFinish: (text: string, reasoning?: string) => void
 	}
+	listen<ConversationTitle>('conversation_title', (event) => {
+		const conversation = conversations.value.find(c => c.id === event.payload.conversation_id)
+		if (conversation) conversation.name = event.payload.name
+	})
+
 	const sendMessage = async (message: Omit<Message, 'id'>, { beforeSend, onReceiving, onFinish }: Partial<SendMessageCallbacks> = {}, parentMessageId = lastMessageId.value ?? undefined): Promise<void> => {
+		const conversationId = currentConversationId.value
+		const isFirstExchange = !parentMessageId
 		const userMessageId = await addMessage(message, parentMessageId, true);
 
 		const botMessage: Omit<Message, "id"> = {
@@ -61,8 +75,10 @@ export const useChatStore = defineStore('chat', () => {
 		try {
 			streamResponse(
 				displayedMessages.value.map((msg) => ({
-					role: msg.sender === "user" ? "user" : "assistant",
+					role: msg.sender,
 					content: msg.text,
+					reasoning: msg.reasoning,
+					compaction: messages.value.get(msg.id)?.compaction,
 				})),
 				chosenModel.value!,
 				chosenProvider.value!,
@@ -77,7 +93,12 @@ export const useChatStore = defineStore('chat', () => {
 					if (onReceiving) onReceiving(chunk, true)
 				},
 				() => {
-					updateMessage(botMessageId, responseText, reasoningText);
+					updateMessage(botMessageId, responseText, reasoningText).then(() => {
+						// The backend skips this unless titles are enabled and the user has not named the conversation
+						if (isFirstExchange && conversationId) return Commands.generateTitle(conversationId)
+					}).catch((err) => {
+						console.error('[ChatStore] Failed to generate title:', err, { conversationId })
+					});
 					if (onFinish) onFinish(responseText, !!reasoningText ? reasoningText : undefined);
 				},
 				true,
@@ -115,8 +136,10 @@ export const useChatStore = defineStore('chat', () => {
 		try {
 			streamResponse(
 				displayedMessages.value.map((msg) => ({
-					role: msg.sender === "user" ? "user" : "assistant",
+					role: msg.sender,
 					content: msg.text,
+					reasoning: msg.reasoning,
+					compaction: messages.value.get(msg.id)?.compaction,
 				})),
 				chosenModel.value!,
 				chosenProvider.value!,
@@ -177,6 +200,18 @@ export const useChatStore = defineStore('chat', () => {
 		return path;
 	}
 
+	// The sibling choices that lead from the root down to `messageId`
+	const getDecisionsTo = (messageId: string) => {
+		if (!threadTree.hasNode(messageId)) return []
+		const path: number[] = []
+		let node = messageId
+		while (threadTree.getParentId(node)) {
+			path.unshift(threadTree.getNodeSiblingOrder(node))
+			node = threadTree.getParentId(node)!
+		}
+		return path
+	}
+
 	const getDisplayedMessageListIds = (fullDecisions: readonly number[], root: string) => {
 		const messagesLocal: { id: string, hasPrev: boolean, hasNext: boolean }[] = []
 		let node = root
@@ -241,6 +276,22 @@ export const useChatStore = defineStore('chat', () => {
 		console.timeEnd(timingIdentifier)
 	})
 
+	// Remember the branch on display, so reopening the conversation restores it
+	watch(lastMessageId, (leafId) => {
+		const conversationId = currentConversationId.value
+		if (!leafId || !conversationId) return
+		const conversation = conversations.value.find(c => c.id === conversationId)
+		if (conversation?.active_leaf_id === leafId) return
+
+		Commands.setActiveLeaf(conversationId, leafId)
+			.then(() => {
+				if (conversation) conversation.active_leaf_id = leafId
+			})
+			.catch((err) => {
+				console.error('[ChatStore] Failed to set the active branch:', err, { conversationId, leafId })
+			})
+	})
+
 	const loadThreadTree = async (conversationId: string) => {
 		return new Promise<void>((resolve, reject) => {
 			Commands.getThreadTree(conversationId)
@@ -301,7 +352,8 @@ export const useChatStore = defineStore('chat', () => {
 			await loadThreadTree(conversationId)
 
 			// root message has been set in loadThreadTree
-			threadTreeDecisions.value = getDefaultThreadTreeDecisions(rootMessageId.value!, threadTreeDecisions.value)
+			const activeLeafId = conversations.value.find(c => c.id === conversationId)?.active_leaf_id
+			threadTreeDecisions.value = getDefaultThreadTreeDecisions(rootMessageId.value!, activeLeafId ? getDecisionsTo(activeLeafId) : threadTreeDecisions.value)
 			console.timeEnd(identifier)
 		}
 		catch (err) {
@@ -313,7 +365,8 @@ export const useChatStore = defineStore('chat', () => {
 		return new Promise<string>((resolve, reject) => {
 			Commands.createConversation(name, description)
 				.then((id) => {
-					conversations.value.push({ id, name })
+					const now = Math.round(Date.now() / 1000)
+					conversations.value.unshift({ id, name, created_at: now, updated_at: now, pinned: false, archived: false })
 					console.log('[ChatStore] Conversation created successfully:', { id, name })
 					resolve(id)
 				})
@@ -365,11 +418,15 @@ export const useChatStore = defineStore('chat', () => {
 		})
 	}
 
-	const listConversations = () => {
+	// Lists the first page of conversations matching `query`; later pages are
+	// appended by loadMoreConversations
+	const listConversations = (query: ConversationQuery = conversationQuery.value) => {
 		return new Promise<Conversation[]>((resolve, reject) => {
-			Commands.listConversations()
+			Commands.listConversations({ ...query, limit: CONVERSATION_PAGE_SIZE, offset: 0 })
 				.then((convs) => {
+					conversationQuery.value = query
 					conversations.value = convs
+					hasMoreConversations.value = convs.length === CONVERSATION_PAGE_SIZE
 					resolve(convs)
 				})
 				.catch((err) => {
@@ -379,6 +436,23 @@ export const useChatStore = defineStore('chat', () => {
 		})
 	}
 
+	const loadMoreConversations = () => {
+		if (!hasMoreConversations.value) return Promise.resolve([])
+		return new Promise<Conversation[]>((resolve, reject) => {
+			Commands.listConversations({ ...conversationQuery.value, limit: CONVERSATION_PAGE_SIZE, offset: conversations.value.length })
+				.then((convs) => {
+					const loaded = new Set(conversations.value.map(c => c.id))
+					conversations.value.push(...convs.filter(c => !loaded.has(c.id)))
+					hasMoreConversations.value = convs.length === CONVERSATION_PAGE_SIZE
+					resolve(convs)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to load more conversations:', err)
+					reject(err)
+				})
+		})
+	}
+
 	const updateConversation = (id: string, newMetaData: Partial<Omit<Omit<Conversation, 'id'>, 'entry_message_id'>>) => {
 		return new Promise<void>((resolve, reject) => {
 			Commands.updateConversation(id, newMetaData)
@@ -387,8 +461,14 @@ export const useChatStore = defineStore('chat', () => {
 					if (conversation) {
 						conversation.name = newMetaData.name || conversation.name
 						conversation.description = newMetaData.description || conversation.description
+						conversation.pinned = newMetaData.pinned ?? conversation.pinned
+						conversation.archived = newMetaData.archived ?? conversation.archived
 						resolve()
 					}
+					// Pinning reorders the list and archiving moves the conversation out of it
+					if (newMetaData.pinned !== undefined || newMetaData.archived !== undefined) {
+						listConversations()
+					}
 				})
 				.catch((err) => {
 					console.error('[ChatStore] Failed to update conversation:', err)
@@ -405,6 +485,219 @@ export const useChatStore = defineStore('chat', () => {
 		rootMessageId.value = null
 	}
 
+	const moveConversationToFolder = (id: string, folderId?: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.moveConversationToFolder(id, folderId)
+				.then(() => {
+					const conversation = conversations.value.find(c => c.id === id)
+					if (conversation) conversation.folder_id = folderId
+					listConversations()
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to move conversation to folder:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const listFolders = () => {
+		return new Promise<Folder[]>((resolve, reject) => {
+			Commands.listFolders()
+				.then((list) => {
+					folders.value = list
+					resolve(list)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to list folders:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const createFolder = (name: string, parentId?: string) => {
+		return new Promise<string>((resolve, reject) => {
+			Commands.createFolder(name, parentId)
+				.then((id) => {
+					listFolders()
+					resolve(id)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to create folder:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const renameFolder = (id: string, name: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.renameFolder(id, name)
+				.then(() => {
+					const folder = folders.value.find(f => f.id === id)
+					if (folder) folder.name = name
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to rename folder:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const moveFolder = (id: string, parentId?: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.moveFolder(id, parentId)
+				.then(() => {
+					const folder = folders.value.find(f => f.id === id)
+					if (folder) folder.parent_id = parentId
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to move folder:', err)
+					reject(err)
+				})
+		})
+	}
+
+	// Subfolders and conversations of a deleted folder move up to its parent
+	const deleteFolder = (id: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.deleteFolder(id)
+				.then(() => {
+					const folder = conversationQuery.value.folder
+					const query = typeof folder === 'object' && folder.folder === id
+						? { ...conversationQuery.value, folder: undefined }
+						: conversationQuery.value
+					listFolders()
+					listConversations(query)
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to delete folder:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const listTags = () => {
+		return new Promise<Tag[]>((resolve, reject) => {
+			Commands.listTags()
+				.then((list) => {
+					tags.value = list
+					resolve(list)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to list tags:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const deleteTag = (id: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.deleteTag(id)
+				.then(() => {
+					tags.value = tags.value.filter(t => t.id !== id)
+					if (conversationQuery.value.tags?.includes(id)) {
+						listConversations({ ...conversationQuery.value, tags: conversationQuery.value.tags.filter(t => t !== id) })
+					}
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to delete tag:', err)
+					reject(err)
+				})
+		})
+	}
+
+	// Tags are created on first use, so the tag list is refreshed afterwards
+	const tagConversation = (id: string, name: string) => {
+		return new Promise<Tag>((resolve, reject) => {
+			Commands.addConversationTag(id, name)
+				.then((tag) => {
+					listTags()
+					resolve(tag)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to tag conversation:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const untagConversation = (id: string, tagId: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.removeConversationTag(id, tagId)
+				.then(() => {
+					if (conversationQuery.value.tags?.includes(tagId)) listConversations()
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to untag conversation:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const getConversationTags = (id: string) => {
+		return new Promise<Tag[]>((resolve, reject) => {
+			Commands.getConversationTags(id)
+				.then(resolve)
+				.catch((err) => {
+					console.error('[ChatStore] Failed to get conversation tags:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const tagMessage = (id: string, name: string) => {
+		return new Promise<Tag>((resolve, reject) => {
+			Commands.addMessageTag(id, name)
+				.then((tag) => {
+					listTags()
+					resolve(tag)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to tag message:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const untagMessage = (id: string, tagId: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.removeMessageTag(id, tagId)
+				.then(() => resolve())
+				.catch((err) => {
+					console.error('[ChatStore] Failed to untag message:', err)
+					reject(err)
+				})
+		})
+	}
+
+	const getMessageTags = (id: string) => {
+		return new Promise<Tag[]>((resolve, reject) => {
+			Commands.getMessageTags(id)
+				.then(resolve)
+				.catch((err) => {
+					console.error('[ChatStore] Failed to get message tags:', err)
+					reject(err)
+				})
+		})
+	}
+
+	// Tagged messages across all conversations, for any tag without `tagId`
+	const listTaggedMessages = (tagId?: string) => {
+		return new Promise<TaggedMessage[]>((resolve, reject) => {
+			Commands.listTaggedMessages(tagId)
+				.then(resolve)
+				.catch((err) => {
+					console.error('[ChatStore] Failed to list tagged messages:', err)
+					reject(err)
+				})
+		})
+	}
+
 	const deleteConversation = (id: string) => {
 		return new Promise<void>((resolve, reject) => {
 			Commands.deleteConversation(id)
@@ -455,6 +748,70 @@ export const useChatStore = defineStore('chat', () => {
 		})
 	}
 
+	const moveSubtree = async (id: string, newParentId?: string) => {
+		const conversationId = currentConversationId.value
+		if (!conversationId) return Promise.reject("No conversation is open")
+
+		try {
+			await Commands.moveSubtree(id, newParentId)
+			await loadThreadTree(conversationId)
+			// Keep the moved message on display
+			rewriteThreadTreeDecision(getDecisionsTo(id))
+			console.log('[ChatStore] Subtree moved successfully:', { id, newParentId })
+		}
+		catch (err) {
+			console.error('[ChatStore] Failed to move subtree:', err, { id, newParentId })
+			return Promise.reject(err)
+		}
+	}
+
+	const forkConversation = async (messageId: string, name: string, includeSubtree = false) => {
+		try {
+			const id = await Commands.forkConversation(messageId, name, includeSubtree)
+			await listConversations()
+			console.log('[ChatStore] Conversation forked successfully:', { id, messageId })
+			return id
+		}
+		catch (err) {
+			console.error('[ChatStore] Failed to fork conversation:', err, { messageId })
+			return Promise.reject(err)
+		}
+	}
+
+	const compactMessage = (id: string) => {
+		return new Promise<Compaction>((resolve, reject) => {
+			Commands.compactConversation(id, chosenModel.value!, chosenProvider.value!)
+				.then((compaction) => {
+					const message = messages.value.get(id)
+					if (message) messages.value.set(id, { ...message, compaction })
+
+					console.log('[ChatStore] Messages above compacted successfully:', { id })
+					resolve(compaction)
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to compact messages:', err, { id })
+					reject(err)
+				})
+		})
+	}
+
+	const deleteCompaction = (id: string) => {
+		return new Promise<void>((resolve, reject) => {
+			Commands.deleteCompaction(id)
+				.then(() => {
+					const message = messages.value.get(id)
+					if (message) messages.value.set(id, { ...message, compaction: undefined })
+
+					console.log('[ChatStore] Compaction deleted successfully:', { id })
+					resolve()
+				})
+				.catch((err) => {
+					console.error('[ChatStore] Failed to delete compaction:', err, { id })
+					reject(err)
+				})
+		})
+	}
+
 	const deleteMessage = (id: string) => {
 		return new Promise<string | null>((resolve, reject) => {
 			Commands.deleteMessage(id, false)
@@ -494,11 +851,35 @@ export const useChatStore = defineStore('chat', () => {
 		getMessage,
 		createConversation,
 		listConversations,
+		loadMoreConversations,
+		conversationQuery,
+		hasMoreConversations,
+		folders,
+		moveConversationToFolder,
+		listFolders,
+		createFolder,
+		renameFolder,
+		moveFolder,
+		deleteFolder,
+		tags,
+		listTags,
+		deleteTag,
+		tagConversation,
+		untagConversation,
+		getConversationTags,
+		tagMessage,
+		untagMessage,
+		getMessageTags,
+		listTaggedMessages,
 		updateConversation,
 		deleteConversation,
 		conversations,
 		updateMessage,
 		deleteMessage,
+		moveSubtree,
+		forkConversation,
+		compactMessage,
+		deleteCompaction,
 		currentConversationId,
 		loadThreadTree,
 		clearUserInput,